@@ -0,0 +1,41 @@
+#![feature(test)]
+
+extern crate test;
+
+use aoc_2020::interpreter::*;
+use test::Bencher;
+
+fn day08() -> Program {
+    Program::parse_file("./inputs/day08.txt").unwrap()
+}
+
+/// A loop that legitimately revisits its instructions until the step limit stops it.
+fn counter() -> (Program, ExecutionLimits) {
+    let p = Program::parse_lines(&["acc +1", "nop +0", "jmp -2"]).unwrap();
+    (p, ExecutionLimits::unlimited().max_steps(1_000_000))
+}
+
+#[bench]
+fn day08_interpreted(b: &mut Bencher) {
+    let p = day08();
+    b.iter(|| VirtualMachine::new(&p).execute());
+}
+
+#[bench]
+fn day08_compiled(b: &mut Bencher) {
+    let p = day08();
+    b.iter(|| CompiledMachine::new(&CompiledProgram::new(&p)).execute());
+}
+
+#[bench]
+fn counter_interpreted(b: &mut Bencher) {
+    let (p, limits) = counter();
+    b.iter(|| VirtualMachine::with_limits(&p, limits).execute());
+}
+
+#[bench]
+fn counter_compiled(b: &mut Bencher) {
+    let (p, limits) = counter();
+    let c = CompiledProgram::new(&p);
+    b.iter(|| CompiledMachine::with_limits(&c, limits).execute());
+}
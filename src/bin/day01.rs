@@ -13,10 +13,11 @@ fn part1() {
     const GOAL: i32 = 2020;
 
     let data = read_input();
-    let (x, y) = Pairwise::from(&data, &data)
-        .filter(|(&x, &y)| x + y == GOAL)
-        .next()
+    let pair = data
+        .combinations(2)
+        .find(|pair| pair[0] + pair[1] == GOAL)
         .unwrap();
+    let (x, y) = (pair[0], pair[1]);
     println!("Part 1: {} * {} = {}", x, y, x * y);
 }
 
@@ -24,11 +25,11 @@ fn part2() {
     const GOAL: i32 = 2020;
 
     let data = read_input();
-    let pairs = Pairwise::from(&data, &data);
-    let ((x, y), z) = Pairwise::from(pairs, &data)
-        .filter(|((&x, &y), &z)| x + y + z == GOAL)
-        .next()
+    let triple = data
+        .combinations(3)
+        .find(|t| t[0] + t[1] + t[2] == GOAL)
         .unwrap();
+    let (x, y, z) = (triple[0], triple[1], triple[2]);
     println!("Part 2: {} * {} * {} = {}", x, y, z, x * y * z);
 }
 
@@ -1,39 +1,19 @@
 use aoc_2020::interpreter::*;
 
-fn mutate(i: &Instruction) -> Instruction {
-    match i {
-        Instruction::Nop(i) => Instruction::Jmp(*i),
-        Instruction::Jmp(i) => Instruction::Nop(*i),
-        x => *x,
-    }
-}
-
 fn part1() {
     let program = Program::parse_file("./inputs/day08.txt").unwrap();
     let mut vm = VirtualMachine::new(&program);
     let result = match vm.execute() {
-        Err(ExecutionError::InfiniteLoop(_)) => vm.current_state().accumulator,
+        Err(ExecutionError::InfiniteLoop(_)) => vm.current_state().accumulator(),
         _ => panic!("not looped!"),
     };
     println!("Part 1: {}", result);
 }
 
 fn part2() {
-    let mut program = Program::parse_file("./inputs/day08.txt").unwrap();
-    for i in 0..program.len() {
-        program.set_instr(i, mutate(program.get_instr(i).unwrap()));
-        {
-            let mut vm = VirtualMachine::new(&program);
-            match vm.execute() {
-                Ok(i) => {
-                    println!("Part 2: {}", i);
-                    break;
-                }
-                _ => {}
-            };
-        }
-        program.set_instr(i, mutate(program.get_instr(i).unwrap()));
-    }
+    let program = Program::parse_file("./inputs/day08.txt").unwrap();
+    let repairs = RepairSearch::new().find_all(&program).unwrap();
+    println!("Part 2: {}", repairs[0].accumulator);
 }
 
 fn main() {
@@ -10,7 +10,8 @@ fn read_input() -> Vec<i64> {
 }
 
 fn try_find_pair(data: &[i64], goal: i64) -> bool {
-    Pairwise::from(data, data).any(|(a, b)| a != b && a + b == goal)
+    data.combinations(2)
+        .any(|p| p[0] != p[1] && p[0] + p[1] == goal)
 }
 
 fn try_match_contiguous_list(data: &[i64], goal: i64, start_at: usize) -> Option<&[i64]> {
@@ -0,0 +1,27 @@
+use aoc_2020::interpreter::*;
+use std::{env, fs, io, process};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 || args.len() > 3 {
+        eprintln!("Usage: {} <program> [script]", args[0]);
+        process::exit(2);
+    }
+
+    let set = InstructionSet::extended();
+    let program = Program::parse_file_all_with(args[1].as_str(), &set).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    let mut debugger = Debugger::new(&program);
+    let stdout = io::stdout();
+    let result = match args.get(2) {
+        Some(script) => fs::File::open(script)
+            .and_then(|f| debugger.run(io::BufReader::new(f), stdout.lock(), true)),
+        None => debugger.run(io::stdin().lock(), stdout.lock(), false),
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
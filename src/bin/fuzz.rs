@@ -0,0 +1,30 @@
+use aoc_2020::interpreter::*;
+use std::{env, process};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let number = |at: usize, default: u64| match args.get(at) {
+        Some(a) => a.parse().unwrap_or_else(|_| {
+            eprintln!("Usage: {} [cases] [seed]", args[0]);
+            process::exit(2);
+        }),
+        None => default,
+    };
+    let cases = number(1, 10_000);
+    let seed = number(2, 0);
+    let config = GeneratorConfig::new();
+
+    let bad_texts = fuzz_parser(&config, seed, cases);
+    for s in &bad_texts {
+        println!("Parser misbehaves on the text generated from seed {}", s);
+    }
+    match fuzz_execution(&config, seed, cases) {
+        Some(failure) => {
+            let path = failure.save(REGRESSIONS_DIR).unwrap();
+            println!("VM disagrees with the reference, saved {}", path.display());
+            process::exit(1);
+        }
+        None if bad_texts.is_empty() => println!("{} cases passed", cases),
+        None => process::exit(1),
+    }
+}
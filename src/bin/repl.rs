@@ -0,0 +1,33 @@
+use aoc_2020::interpreter::*;
+use std::{env, io, process};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() > 2 {
+        eprintln!("Usage: {} [program]", args[0]);
+        process::exit(2);
+    }
+
+    let profile = MachineProfile::new()
+        .register("a")
+        .register("b")
+        .register("c")
+        .register("d")
+        .memory(1024);
+    let mut repl = Repl::new(InstructionSet::extended()).with_profile(profile);
+    let stdout = io::stdout();
+    if let Some(path) = args.get(1) {
+        match repl.execute(&format!(":load {}", path)) {
+            Some(Ok(s)) => print!("{}", s),
+            Some(Err(e)) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+            None => {}
+        }
+    }
+    if let Err(e) = repl.run(io::stdin().lock(), stdout.lock(), false) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
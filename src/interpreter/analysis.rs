@@ -0,0 +1,356 @@
+use std::{
+    collections::VecDeque,
+    fmt::{self, Write},
+};
+
+use super::{AnalysisError, AnalysisResult, Flow, Program};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Termination {
+    Always,
+    Never,
+    Unknown,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct InvalidJump {
+    pub at: usize,
+    pub target: isize,
+}
+
+/// Static control-flow graph of a program. Node `program.len()` is the exit node; instructions
+/// whose successors depend on the state (see `Operation::successors`) have no edges and are
+/// reported as dynamic.
+pub struct ControlFlowGraph<'a> {
+    program: &'a Program,
+    successors: Vec<Vec<usize>>,
+    invalid_jumps: Vec<InvalidJump>,
+    dynamic: Vec<usize>,
+    reachable: Vec<bool>,
+}
+
+impl<'a> ControlFlowGraph<'a> {
+    pub fn new(program: &'a Program) -> Self {
+        let n = program.len();
+        let mut successors = vec![Vec::new(); n + 1];
+        let mut invalid_jumps = Vec::new();
+        let mut dynamic = Vec::new();
+        for (i, succ) in successors.iter_mut().enumerate().take(n) {
+            let flows = match program.get_instr(i).unwrap().successors() {
+                Some(f) => f,
+                None => {
+                    dynamic.push(i);
+                    continue;
+                }
+            };
+            for flow in flows {
+                let target = match flow {
+                    Flow::Next => (i as isize).saturating_add(1),
+                    Flow::Jump(offset) => (i as isize).saturating_add(offset),
+                    Flow::Halt => n as isize,
+                };
+                if target < 0 || target as usize > n {
+                    invalid_jumps.push(InvalidJump { at: i, target });
+                } else if !succ.contains(&(target as usize)) {
+                    succ.push(target as usize);
+                }
+            }
+        }
+
+        let mut cfg = ControlFlowGraph {
+            program,
+            successors,
+            invalid_jumps,
+            dynamic,
+            reachable: vec![false; n + 1],
+        };
+        cfg.mark_reachable();
+        cfg
+    }
+
+    fn mark_reachable(&mut self) {
+        let mut queue = VecDeque::new();
+        queue.push_back(0);
+        self.reachable[0] = true;
+        while let Some(i) = queue.pop_front() {
+            for &s in &self.successors[i] {
+                if !self.reachable[s] {
+                    self.reachable[s] = true;
+                    queue.push_back(s);
+                }
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.program.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.program.len() == 0
+    }
+
+    pub fn successors(&self, at: usize) -> &[usize] {
+        &self.successors[at]
+    }
+
+    pub fn invalid_jumps(&self) -> &[InvalidJump] {
+        &self.invalid_jumps
+    }
+
+    pub fn dynamic_instructions(&self) -> &[usize] {
+        &self.dynamic
+    }
+
+    pub fn is_reachable(&self, at: usize) -> bool {
+        self.reachable[at] || self.reaches_dynamic()
+    }
+
+    /// Instructions that can never be executed. Once a dynamic instruction is reachable, control
+    /// can go anywhere, so nothing is reported.
+    pub fn unreachable(&self) -> Vec<usize> {
+        (0..self.len()).filter(|i| !self.is_reachable(*i)).collect()
+    }
+
+    pub fn strongly_connected_components(&self) -> Vec<Vec<usize>> {
+        let n = self.len();
+        let mut index = vec![usize::MAX; n];
+        let mut lowlink = vec![0; n];
+        let mut on_stack = vec![false; n];
+        let mut stack = Vec::new();
+        let mut counter = 0;
+        let mut components = Vec::new();
+
+        for start in 0..n {
+            if index[start] != usize::MAX {
+                continue;
+            }
+            let mut calls = vec![(start, 0)];
+            index[start] = counter;
+            lowlink[start] = counter;
+            counter += 1;
+            stack.push(start);
+            on_stack[start] = true;
+
+            while let Some(&(v, child)) = calls.last() {
+                if let Some(&w) = self.successors[v].get(child) {
+                    calls.last_mut().unwrap().1 += 1;
+                    if w == n {
+                        continue;
+                    }
+                    if index[w] == usize::MAX {
+                        index[w] = counter;
+                        lowlink[w] = counter;
+                        counter += 1;
+                        stack.push(w);
+                        on_stack[w] = true;
+                        calls.push((w, 0));
+                    } else if on_stack[w] {
+                        lowlink[v] = lowlink[v].min(index[w]);
+                    }
+                } else {
+                    calls.pop();
+                    if let Some(&(u, _)) = calls.last() {
+                        lowlink[u] = lowlink[u].min(lowlink[v]);
+                    }
+                    if lowlink[v] == index[v] {
+                        let mut component = Vec::new();
+                        while let Some(w) = stack.pop() {
+                            on_stack[w] = false;
+                            component.push(w);
+                            if w == v {
+                                break;
+                            }
+                        }
+                        component.sort_unstable();
+                        components.push(component);
+                    }
+                }
+            }
+        }
+        components.sort();
+        components
+    }
+
+    /// Strongly connected components that contain a cycle.
+    pub fn loops(&self) -> Vec<Vec<usize>> {
+        self.strongly_connected_components()
+            .into_iter()
+            .filter(|c| c.len() > 1 || self.successors[c[0]].contains(&c[0]))
+            .collect()
+    }
+
+    pub fn termination(&self) -> Termination {
+        let n = self.len();
+        if self.reaches_dynamic() {
+            Termination::Unknown
+        } else if !self.reachable[n] {
+            Termination::Never
+        } else if self.invalid_jumps.iter().any(|j| self.reachable[j.at])
+            || self.loops().iter().any(|l| self.reachable[l[0]])
+        {
+            Termination::Unknown
+        } else {
+            Termination::Always
+        }
+    }
+
+    /// Rejects programs that contain out-of-bounds jumps or can never terminate.
+    pub fn verify(&self) -> AnalysisResult<()> {
+        if let Some(j) = self.invalid_jumps.first() {
+            Err(AnalysisError::InvalidJump(j.at, j.target))
+        } else if self.termination() == Termination::Never {
+            Err(AnalysisError::NeverTerminates)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut s = String::new();
+        self.write_dot(&mut s).unwrap();
+        s
+    }
+
+    pub fn write_dot<W: Write>(&self, out: &mut W) -> fmt::Result {
+        let n = self.len();
+        writeln!(out, "digraph program {{")?;
+        writeln!(out, "    node [shape=box, fontname=\"monospace\"];")?;
+        for i in 0..n {
+            let mut attrs = format!("label=\"{}: {}\"", i, self.program.get_instr(i).unwrap());
+            if !self.is_reachable(i) {
+                attrs.push_str(", style=dashed");
+            }
+            if self.dynamic.contains(&i) {
+                attrs.push_str(", shape=octagon");
+            }
+            writeln!(out, "    n{} [{}];", i, attrs)?;
+        }
+        writeln!(out, "    n{} [label=\"end\", shape=doublecircle];", n)?;
+        for (i, succ) in self.successors.iter().enumerate() {
+            for s in succ {
+                writeln!(out, "    n{} -> n{};", i, s)?;
+            }
+        }
+        for (k, j) in self.invalid_jumps.iter().enumerate() {
+            writeln!(
+                out,
+                "    invalid{} [label=\"{}\", shape=plaintext, fontcolor=red];",
+                k, j.target
+            )?;
+            writeln!(out, "    n{} -> invalid{} [color=red];", j.at, k)?;
+        }
+        writeln!(out, "}}")
+    }
+
+    fn reaches_dynamic(&self) -> bool {
+        self.dynamic.iter().any(|i| self.reachable[*i])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{Instruction, InstructionSet};
+
+    const EXAMPLE: [&str; 9] = [
+        "nop +0", "acc +1", "jmp +4", "acc +3", "jmp -3", "acc -99", "acc +1", "jmp -4", "acc +6",
+    ];
+
+    #[test]
+    fn analyses_looping_program() {
+        let p = Program::parse_lines(&EXAMPLE).unwrap();
+        let cfg = ControlFlowGraph::new(&p);
+        assert_eq!(vec![5, 8], cfg.unreachable());
+        assert_eq!(vec![vec![1, 2, 3, 4, 6, 7]], cfg.loops());
+        assert!(cfg.invalid_jumps().is_empty());
+        assert_eq!(Termination::Never, cfg.termination());
+        assert_eq!(Err(AnalysisError::NeverTerminates), cfg.verify());
+        assert_eq!(4, cfg.strongly_connected_components().len());
+    }
+
+    #[test]
+    fn analyses_terminating_program() {
+        let mut p = Program::parse_lines(&EXAMPLE).unwrap();
+        p.set_instr(7, Instruction::Nop(-4));
+        let cfg = ControlFlowGraph::new(&p);
+        assert_eq!(vec![3, 4, 5], cfg.unreachable());
+        assert!(cfg.loops().is_empty());
+        assert_eq!(Termination::Always, cfg.termination());
+        assert_eq!(Ok(()), cfg.verify());
+    }
+
+    #[test]
+    fn finds_invalid_jumps() {
+        let p = Program::parse_lines(&["jmp +2", "jmp -2", "jmp +5", "jmp +0"]).unwrap();
+        let cfg = ControlFlowGraph::new(&p);
+        assert_eq!(
+            &[
+                InvalidJump { at: 1, target: -1 },
+                InvalidJump { at: 2, target: 7 }
+            ],
+            cfg.invalid_jumps()
+        );
+        assert_eq!(vec![1, 3], cfg.unreachable());
+        assert_eq!(vec![vec![3]], cfg.loops());
+        assert_eq!(Err(AnalysisError::InvalidJump(1, -1)), cfg.verify());
+        assert_eq!(Termination::Never, cfg.termination());
+
+        let p = Program::parse_lines(&["nop +0", "jmp +9223372036854775807"]).unwrap();
+        let cfg = ControlFlowGraph::new(&p);
+        assert_eq!(
+            &[InvalidJump {
+                at: 1,
+                target: isize::MAX
+            }],
+            cfg.invalid_jumps()
+        );
+    }
+
+    #[test]
+    fn handles_custom_instructions() {
+        let set = InstructionSet::extended();
+        let p = Program::parse_lines_with(&["acc +3", "acc -1", "jnz -1", "hlt", "nop +0"], &set)
+            .unwrap();
+        let cfg = ControlFlowGraph::new(&p);
+        assert_eq!(&[3, 1][..], cfg.successors(2));
+        assert_eq!(&[5][..], cfg.successors(3));
+        assert_eq!(vec![4], cfg.unreachable());
+        assert_eq!(vec![vec![1, 2]], cfg.loops());
+        assert_eq!(Termination::Unknown, cfg.termination());
+
+        let p = Program::parse_lines_with(&["jmpa", "nop +0"], &set).unwrap();
+        let cfg = ControlFlowGraph::new(&p);
+        assert_eq!(&[0], cfg.dynamic_instructions());
+        assert!(cfg.unreachable().is_empty());
+        assert_eq!(Termination::Unknown, cfg.termination());
+    }
+
+    #[test]
+    fn empty_program() {
+        let p = Program::parse_lines(&[]).unwrap();
+        let cfg = ControlFlowGraph::new(&p);
+        assert!(cfg.is_empty());
+        assert_eq!(Termination::Always, cfg.termination());
+    }
+
+    #[test]
+    fn exports_dot() {
+        let p = Program::parse_lines(&["nop +0", "jmp -5", "acc +1"]).unwrap();
+        let cfg = ControlFlowGraph::new(&p);
+        assert_eq!(
+            "digraph program {\n\
+             \x20   node [shape=box, fontname=\"monospace\"];\n\
+             \x20   n0 [label=\"0: nop +0\"];\n\
+             \x20   n1 [label=\"1: jmp -5\"];\n\
+             \x20   n2 [label=\"2: acc +1\", style=dashed];\n\
+             \x20   n3 [label=\"end\", shape=doublecircle];\n\
+             \x20   n0 -> n1;\n\
+             \x20   n2 -> n3;\n\
+             \x20   invalid0 [label=\"-4\", shape=plaintext, fontcolor=red];\n\
+             \x20   n1 -> invalid0 [color=red];\n\
+             }\n",
+            cfg.to_dot()
+        );
+    }
+}
@@ -0,0 +1,189 @@
+use std::{convert::TryFrom, sync::Arc};
+
+use num_bigint::{BigInt, Sign};
+
+use super::{ExecutionError, ExecutionResult, ProgramState};
+
+/// What happens when the accumulator leaves the range of an `i64`.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Arithmetic {
+    /// Fail with `ExecutionError::Overflow`.
+    #[default]
+    Checked,
+    /// Wrap around in two's complement.
+    Wrapping,
+    /// Stay at `i64::MIN` or `i64::MAX`.
+    Saturating,
+    /// Keep the exact value, see `ProgramState::exact_accumulator`.
+    Unbounded,
+}
+
+struct Operator {
+    checked: fn(i64, i64) -> Option<i64>,
+    wrapping: fn(i64, i64) -> i64,
+    saturating: fn(i64, i64) -> i64,
+    exact: fn(BigInt, BigInt) -> BigInt,
+}
+
+const ADD: Operator = Operator {
+    checked: i64::checked_add,
+    wrapping: i64::wrapping_add,
+    saturating: i64::saturating_add,
+    exact: |a, b| a + b,
+};
+
+const SUB: Operator = Operator {
+    checked: i64::checked_sub,
+    wrapping: i64::wrapping_sub,
+    saturating: i64::saturating_sub,
+    exact: |a, b| a - b,
+};
+
+const MUL: Operator = Operator {
+    checked: i64::checked_mul,
+    wrapping: i64::wrapping_mul,
+    saturating: i64::saturating_mul,
+    exact: |a, b| a * b,
+};
+
+impl ProgramState {
+    pub fn add_to_accumulator(&mut self, value: i64) -> ExecutionResult<()> {
+        self.combine(&ADD, value)
+    }
+
+    pub fn subtract_from_accumulator(&mut self, value: i64) -> ExecutionResult<()> {
+        self.combine(&SUB, value)
+    }
+
+    pub fn multiply_accumulator(&mut self, value: i64) -> ExecutionResult<()> {
+        self.combine(&MUL, value)
+    }
+
+    /// The accumulator, or its lowest 64 bits if it has grown past an `i64` under
+    /// `Arithmetic::Unbounded`.
+    pub fn accumulator(&self) -> i64 {
+        self.accumulator
+    }
+
+    pub fn set_accumulator(&mut self, value: i64) {
+        self.accumulator = value;
+        self.wide = None;
+    }
+
+    /// The accumulator without truncation. Only `Arithmetic::Unbounded` lets it grow past an
+    /// `i64`.
+    pub fn exact_accumulator(&self) -> BigInt {
+        match &self.wide {
+            Some(v) => (**v).clone(),
+            None => BigInt::from(self.accumulator),
+        }
+    }
+
+    fn combine(&mut self, op: &Operator, value: i64) -> ExecutionResult<()> {
+        let acc = self.accumulator;
+        match self.arithmetic {
+            Arithmetic::Checked => {
+                self.accumulator =
+                    (op.checked)(acc, value).ok_or(ExecutionError::Overflow(self.instruction))?
+            }
+            Arithmetic::Wrapping => self.accumulator = (op.wrapping)(acc, value),
+            Arithmetic::Saturating => self.accumulator = (op.saturating)(acc, value),
+            Arithmetic::Unbounded => match (&self.wide, (op.checked)(acc, value)) {
+                (None, Some(v)) => self.accumulator = v,
+                _ => self.set_exact((op.exact)(self.exact_accumulator(), BigInt::from(value))),
+            },
+        }
+        Ok(())
+    }
+
+    fn set_exact(&mut self, value: BigInt) {
+        match i64::try_from(&value) {
+            Ok(v) => self.set_accumulator(v),
+            Err(_) => {
+                let (sign, digits) = value.to_u64_digits();
+                let low = digits[0] as i64;
+                self.accumulator = if sign == Sign::Minus {
+                    low.wrapping_neg()
+                } else {
+                    low
+                };
+                self.wide = Some(Arc::new(value));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{
+        ExecutionLimits, InstructionSet, MachineProfile, Program, VirtualMachine,
+    };
+
+    fn run(lines: &[&str], arithmetic: Arithmetic) -> (ExecutionResult<i64>, ProgramState) {
+        let p = Program::parse_lines_with(lines, &InstructionSet::extended()).unwrap();
+        let state = MachineProfile::new().arithmetic(arithmetic).initial_state();
+        let mut vm = VirtualMachine::with_state(&p, ExecutionLimits::new(), state);
+        (vm.execute(), vm.current_state().clone())
+    }
+
+    const OVERFLOWING: [&str; 3] = ["acc +9223372036854775807", "acc +2", "acc -3"];
+
+    #[test]
+    fn checked() {
+        let (result, state) = run(&OVERFLOWING, Arithmetic::Checked);
+        assert_eq!(Err(ExecutionError::Overflow(1)), result);
+        assert_eq!(i64::MAX, state.accumulator());
+        let (result, _) = run(&["acc -2", "mul +4611686018427387904"], Arithmetic::Checked);
+        assert_eq!(Ok(i64::MIN), result);
+    }
+
+    #[test]
+    fn wrapping_and_saturating() {
+        assert_eq!(Ok(i64::MAX - 1), run(&OVERFLOWING, Arithmetic::Wrapping).0);
+        assert_eq!(
+            Ok(i64::MAX - 3),
+            run(&OVERFLOWING, Arithmetic::Saturating).0
+        );
+        assert_eq!(
+            Ok(i64::MIN),
+            run(
+                &["acc -5", "mul +9223372036854775807"],
+                Arithmetic::Saturating
+            )
+            .0
+        );
+    }
+
+    #[test]
+    fn unbounded() {
+        let (result, state) = run(&OVERFLOWING[..2], Arithmetic::Unbounded);
+        assert_eq!(Ok(i64::MIN + 1), result);
+        assert_eq!(BigInt::from(i64::MAX) + 2, state.exact_accumulator());
+        assert_eq!("@2 acc=9223372036854775809", state.to_string());
+
+        let (result, state) = run(&OVERFLOWING, Arithmetic::Unbounded);
+        assert_eq!(Ok(i64::MAX - 1), result);
+        assert_eq!(state, {
+            let mut s = MachineProfile::new()
+                .arithmetic(Arithmetic::Unbounded)
+                .initial_state();
+            s.instruction = 3;
+            s.set_accumulator(i64::MAX - 1);
+            s
+        });
+
+        let lines = [
+            "acc -9223372036854775807",
+            "mul +1000",
+            "mul -1000",
+            "mul +0",
+        ];
+        let (_, state) = run(&lines[..3], Arithmetic::Unbounded);
+        assert_eq!(
+            BigInt::from(9_223_372_036_854_775_807i64) * 1_000_000,
+            state.exact_accumulator()
+        );
+        assert_eq!(Ok(0), run(&lines, Arithmetic::Unbounded).0);
+    }
+}
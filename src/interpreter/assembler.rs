@@ -0,0 +1,427 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::Write,
+    fs,
+    path::Path,
+};
+
+use super::{
+    CodeParseError, CodeParseResult, Flow, Instruction, InstructionSet, ParseError, Program, Span,
+};
+
+#[derive(Debug, Copy, Clone)]
+struct Token<'a> {
+    text: &'a str,
+    span: Span,
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Symbol {
+    Label(usize),
+    Constant(i64),
+}
+
+struct PendingInstruction<'a> {
+    mnemonic: Token<'a>,
+    operand: Option<Token<'a>>,
+}
+
+fn tokenize(line: usize, text: &str) -> Vec<Token<'_>> {
+    let code = &text[..text.find('#').unwrap_or(text.len())];
+    let mut tokens = Vec::new();
+    let mut word: Option<(usize, usize)> = None;
+    for (column, (at, c)) in code.char_indices().enumerate() {
+        let separator = c.is_whitespace() || c == ':' || c == '=';
+        if let (true, Some((start_at, start))) = (separator, word) {
+            tokens.push(Token {
+                text: &code[start_at..at],
+                span: Span {
+                    line,
+                    start: start + 1,
+                    end: column + 1,
+                },
+            });
+            word = None;
+        }
+        if c == ':' || c == '=' {
+            tokens.push(Token {
+                text: &code[at..at + 1],
+                span: Span {
+                    line,
+                    start: column + 1,
+                    end: column + 2,
+                },
+            });
+        } else if !separator && word.is_none() {
+            word = Some((at, column));
+        }
+    }
+    if let Some((start_at, start)) = word {
+        tokens.push(Token {
+            text: &code[start_at..],
+            span: Span {
+                line,
+                start: start + 1,
+                end: start + code[start_at..].chars().count() + 1,
+            },
+        });
+    }
+    tokens
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+fn join(from: Span, to: Span) -> Span {
+    Span {
+        line: from.line,
+        start: from.start,
+        end: to.end,
+    }
+}
+
+struct Assembler<'a> {
+    symbols: HashMap<&'a str, Symbol>,
+    pending: Vec<PendingInstruction<'a>>,
+}
+
+impl<'a> Assembler<'a> {
+    fn define(&mut self, name: Token<'a>, symbol: Symbol) -> CodeParseResult<()> {
+        if !is_identifier(name.text) {
+            Err(CodeParseError::at_span(
+                name.span,
+                ParseError::InvalidSymbol(name.text.to_string()),
+            ))
+        } else if self.symbols.insert(name.text, symbol).is_some() {
+            Err(CodeParseError::at_span(
+                name.span,
+                ParseError::DuplicateSymbol(name.text.to_string()),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn constant(&self, value: Token<'a>) -> CodeParseResult<i64> {
+        match self.symbols.get(value.text) {
+            Some(Symbol::Constant(v)) => Ok(*v),
+            _ if is_identifier(value.text) => Err(CodeParseError::at_span(
+                value.span,
+                ParseError::UndefinedSymbol(value.text.to_string()),
+            )),
+            _ => value.text.parse().map_err(|_| {
+                CodeParseError::at_span(
+                    value.span,
+                    ParseError::UnparseableLine(value.text.to_string()),
+                )
+            }),
+        }
+    }
+
+    fn statement(&mut self, tokens: &[Token<'a>]) -> CodeParseResult<()> {
+        let mut rest = tokens;
+        while let [label, colon, tail @ ..] = rest {
+            if colon.text != ":" {
+                break;
+            }
+            self.define(*label, Symbol::Label(self.pending.len()))?;
+            rest = tail;
+        }
+
+        match rest {
+            [] => Ok(()),
+            [kw, name, eq, value] if kw.text == "const" && eq.text == "=" => {
+                let value = self.constant(*value)?;
+                self.define(*name, Symbol::Constant(value))
+            }
+            [first, .., last] if first.text == "const" => Err(CodeParseError::at_span(
+                join(first.span, last.span),
+                ParseError::UnparseableLine(
+                    rest.iter().map(|t| t.text).collect::<Vec<_>>().join(" "),
+                ),
+            )),
+            [t, ..] if t.text == ":" || t.text == "=" => Err(CodeParseError::at_span(
+                t.span,
+                ParseError::UnparseableLine(t.text.to_string()),
+            )),
+            [mnemonic] => {
+                self.pending.push(PendingInstruction {
+                    mnemonic: *mnemonic,
+                    operand: None,
+                });
+                Ok(())
+            }
+            [mnemonic, operand] => {
+                self.pending.push(PendingInstruction {
+                    mnemonic: *mnemonic,
+                    operand: Some(*operand),
+                });
+                Ok(())
+            }
+            [mnemonic, first, .., last] => Err(CodeParseError::at_span(
+                join(first.span, last.span),
+                ParseError::UnparseableParameter(
+                    mnemonic.text.to_string(),
+                    rest[1..]
+                        .iter()
+                        .map(|t| t.text)
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                ),
+            )),
+        }
+    }
+
+    fn resolve(
+        &self,
+        index: usize,
+        instr: &PendingInstruction<'a>,
+        set: &InstructionSet,
+    ) -> CodeParseResult<Instruction> {
+        let mnemonic = instr.mnemonic;
+        let (operand, unresolved) = match instr.operand {
+            None => (String::new(), false),
+            Some(t) => match self.symbols.get(t.text) {
+                Some(Symbol::Label(addr)) => {
+                    (format!("{:+}", *addr as isize - index as isize), false)
+                }
+                Some(Symbol::Constant(v)) => (format!("{:+}", v), false),
+                None => (t.text.to_string(), is_identifier(t.text)),
+            },
+        };
+        let operand_span = instr.operand.map(|t| t.span).unwrap_or(mnemonic.span);
+        Instruction::parse_with(&format!("{} {}", mnemonic.text, operand), set).map_err(|e| {
+            let (span, e) = match e {
+                ParseError::UnparseableParameter(..) if unresolved => (
+                    operand_span,
+                    ParseError::UndefinedSymbol(instr.operand.unwrap().text.to_string()),
+                ),
+                e @ ParseError::UnparseableParameter(..) => (operand_span, e),
+                e => (mnemonic.span, e),
+            };
+            CodeParseError::at_span(span, e)
+        })
+    }
+}
+
+impl Program {
+    /// Compiles the labelled source format: `label:` definitions, `const NAME = value`, `#`
+    /// comments and symbolic operands. Labels used as operands become relative offsets.
+    pub fn assemble(source: &str) -> CodeParseResult<Self> {
+        Self::assemble_with(source, &InstructionSet::new())
+    }
+
+    pub fn assemble_with(source: &str, set: &InstructionSet) -> CodeParseResult<Self> {
+        let mut assembler = Assembler {
+            symbols: HashMap::new(),
+            pending: Vec::new(),
+        };
+        for (i, line) in source.lines().enumerate() {
+            assembler.statement(&tokenize(i + 1, line))?;
+        }
+        let statements = assembler
+            .pending
+            .iter()
+            .enumerate()
+            .map(|(i, instr)| assembler.resolve(i, instr, set))
+            .collect::<CodeParseResult<Vec<_>>>()?;
+        Ok(Program::from(statements))
+    }
+
+    pub fn assemble_file<P: AsRef<Path> + Copy>(
+        path: P,
+        set: &InstructionSet,
+    ) -> CodeParseResult<Self> {
+        let source = fs::read_to_string(path).map_err(|e| CodeParseError::from_io(path, e))?;
+        Self::assemble_with(&source, set)
+    }
+
+    pub fn disassemble(&self) -> String {
+        let n = self.len();
+        let targets: BTreeSet<_> = (0..n).filter_map(|i| self.jump_target(i)).collect();
+        let mut s = String::new();
+        for (i, instr) in self.instructions().iter().enumerate() {
+            if targets.contains(&i) {
+                writeln!(s, "L{}:", i).unwrap();
+            }
+            match self.jump_target(i) {
+                Some(t) => writeln!(s, "    {} L{}", instr.mnemonic(), t).unwrap(),
+                None => writeln!(s, "    {}", instr).unwrap(),
+            }
+        }
+        if targets.contains(&n) {
+            writeln!(s, "L{}:", n).unwrap();
+        }
+        s
+    }
+
+    fn jump_target(&self, at: usize) -> Option<usize> {
+        let offset = match self.get_instr(at)? {
+            Instruction::Jmp(o) => *o,
+            Instruction::Custom(op, arg) => {
+                let flows = op.successors(*arg).unwrap_or_default();
+                if !flows.contains(&Flow::Jump(*arg as isize)) {
+                    return None;
+                }
+                *arg as isize
+            }
+            _ => return None,
+        };
+        let target = (at as isize).saturating_add(offset);
+        if target < 0 || target as usize > self.len() {
+            None
+        } else {
+            Some(target as usize)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "\
+# Counts down from LIMIT
+const LIMIT = 3
+const ZERO = 0
+
+start:  acc LIMIT
+        nop ZERO      # does nothing
+loop:
+        acc -1
+        jnz loop
+        jmp end
+        acc +100
+end:
+";
+
+    #[test]
+    fn assembles() {
+        let set = InstructionSet::extended();
+        let p = Program::assemble_with(SOURCE, &set).unwrap();
+        assert_eq!(
+            &[
+                Instruction::Acc(3),
+                Instruction::Nop(0),
+                Instruction::Acc(-1),
+                Instruction::Custom(set.get("jnz").unwrap(), -1),
+                Instruction::Jmp(2),
+                Instruction::Acc(100),
+            ],
+            p.instructions()
+        );
+    }
+
+    #[test]
+    fn assembles_relative_offsets_and_forward_references() {
+        let p = Program::assemble("a: b: jmp c\n  jmp +2\nc: jmp a\n nop b").unwrap();
+        assert_eq!(
+            &[
+                Instruction::Jmp(2),
+                Instruction::Jmp(2),
+                Instruction::Jmp(-2),
+                Instruction::Nop(-3),
+            ],
+            p.instructions()
+        );
+    }
+
+    fn error(source: &str) -> (Span, ParseError) {
+        match Program::assemble(source) {
+            Err(CodeParseError::AtSpan { span, error }) => (span, error),
+            r => panic!("unexpected result {:?}", r.map(|p| p.len())),
+        }
+    }
+
+    #[test]
+    fn reports_spans() {
+        let span = |line, start, end| Span { line, start, end };
+        assert_eq!(
+            (
+                span(2, 3, 6),
+                ParseError::UnknownInstruction("pon".to_string())
+            ),
+            error("nop +0\n  pon +1")
+        );
+        assert_eq!(
+            (
+                span(1, 5, 9),
+                ParseError::UndefinedSymbol("nope".to_string())
+            ),
+            error("jmp nope")
+        );
+        assert_eq!(
+            (
+                span(1, 5, 7),
+                ParseError::UnparseableParameter("acc".to_string(), "1x".to_string())
+            ),
+            error("acc 1x # comment")
+        );
+        assert_eq!(
+            (
+                span(1, 1, 4),
+                ParseError::MissingParameter("jmp".to_string())
+            ),
+            error("jmp")
+        );
+        assert_eq!(
+            (span(3, 1, 2), ParseError::DuplicateSymbol("a".to_string())),
+            error("a: nop +0\nconst b = 1\na: nop +0")
+        );
+        assert_eq!(
+            (span(2, 1, 2), ParseError::DuplicateSymbol("b".to_string())),
+            error("const b = 1\nb: nop +0")
+        );
+        assert_eq!(
+            (span(1, 1, 3), ParseError::InvalidSymbol("1a".to_string())),
+            error("1a: nop +0")
+        );
+        assert_eq!(
+            (
+                span(1, 5, 12),
+                ParseError::UnparseableParameter("jmp".to_string(), "+1 +2".to_string())
+            ),
+            error("jmp +1   +2")
+        );
+        assert_eq!(
+            (
+                span(1, 11, 12),
+                ParseError::UndefinedSymbol("y".to_string())
+            ),
+            error("const x = y")
+        );
+        assert_eq!(
+            (
+                span(1, 1, 8),
+                ParseError::UnparseableLine("const x".to_string())
+            ),
+            error("const x")
+        );
+    }
+
+    #[test]
+    fn round_trips_through_disassembler() {
+        let set = InstructionSet::extended();
+        let p = Program::assemble_with(SOURCE, &set).unwrap();
+        let source = p.disassemble();
+        assert_eq!(
+            "    acc +3\n    nop +0\nL2:\n    acc -1\n    jnz L2\n    jmp L6\n    acc +100\nL6:\n",
+            source
+        );
+        let q = Program::assemble_with(&source, &set).unwrap();
+        assert_eq!(p.instructions(), q.instructions());
+
+        let p = Program::parse_lines(&["jmp +0", "jmp -5", "nop -1", "acc +2", "jmp -3"]).unwrap();
+        let q = Program::assemble(&p.disassemble()).unwrap();
+        assert_eq!(p.instructions(), q.instructions());
+
+        let p = Program::parse_lines(&["nop +0", "jmp +9223372036854775807"]).unwrap();
+        assert_eq!(
+            "    nop +0\n    jmp +9223372036854775807\n",
+            p.disassemble()
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use super::{Instruction, Operation, Program};
+
+/// Builds a `Program` instruction by instruction, without going through the parser.
+#[derive(Default)]
+pub struct ProgramBuilder {
+    statements: Vec<Instruction>,
+}
+
+impl ProgramBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn nop(self, offset: isize) -> Self {
+        self.instruction(Instruction::Nop(offset))
+    }
+
+    pub fn acc(self, value: i64) -> Self {
+        self.instruction(Instruction::Acc(value))
+    }
+
+    pub fn jmp(self, offset: isize) -> Self {
+        self.instruction(Instruction::Jmp(offset))
+    }
+
+    pub fn op(self, op: &'static dyn Operation, operand: i64) -> Self {
+        self.instruction(Instruction::Custom(op, operand))
+    }
+
+    pub fn instruction(mut self, instr: Instruction) -> Self {
+        self.statements.push(instr);
+        self
+    }
+
+    pub fn build(self) -> Program {
+        Program::from(self.statements)
+    }
+}
+
+impl Program {
+    pub fn builder() -> ProgramBuilder {
+        ProgramBuilder::new()
+    }
+}
+
+/// Builds a `Program` from instructions separated by `;`. Operands are literals, optionally
+/// signed, or any single token expression such as a constant or a parenthesized expression.
+/// Register operands are names. Unknown instructions, missing or extra operands and invalid
+/// register names are compile errors.
+///
+/// ```
+/// use aoc_2020::{interpreter::Instruction, program};
+///
+/// const STEP: i64 = 5;
+/// let p = program! { nop +1; acc STEP; mul (STEP - 1); jmp -2; hlt };
+/// assert_eq!(Instruction::Acc(5), *p.get_instr(1).unwrap());
+/// assert_eq!("mul +4", p.get_instr(2).unwrap().to_string());
+/// ```
+///
+/// ```compile_fail
+/// let p = aoc_2020::program! { nop +0; acc +1; jpm -1 };
+/// ```
+///
+/// ```compile_fail
+/// let p = aoc_2020::program! { ld overlong_name };
+/// ```
+#[macro_export]
+macro_rules! program {
+    (@munch [$($out:expr,)*]) => {
+        $crate::interpreter::Program::from(vec![$($out,)*])
+    };
+    (@munch [$($out:expr,)*] ; $($rest:tt)*) => {
+        $crate::program!(@munch [$($out,)*] $($rest)*)
+    };
+    (@munch [$($out:expr,)*] $m:ident ; $($rest:tt)*) => {
+        $crate::program!(@munch [$($out,)* $crate::program!(@instr $m []),] $($rest)*)
+    };
+    (@munch [$($out:expr,)*] $m:ident + $n:tt ; $($rest:tt)*) => {
+        $crate::program!(@munch [$($out,)* $crate::program!(@instr $m [$n]),] $($rest)*)
+    };
+    (@munch [$($out:expr,)*] $m:ident - $n:tt ; $($rest:tt)*) => {
+        $crate::program!(@munch [$($out,)* $crate::program!(@instr $m [-$n]),] $($rest)*)
+    };
+    (@munch [$($out:expr,)*] $m:ident $n:tt ; $($rest:tt)*) => {
+        $crate::program!(@munch [$($out,)* $crate::program!(@instr $m [$n]),] $($rest)*)
+    };
+    (@munch [$($out:expr,)*] $($rest:tt)+) => {
+        compile_error!(concat!("cannot parse instruction ", stringify!($($rest)+)))
+    };
+
+    (@instr nop [$($n:tt)+]) => { $crate::interpreter::Instruction::Nop($($n)+) };
+    (@instr acc [$($n:tt)+]) => { $crate::interpreter::Instruction::Acc($($n)+) };
+    (@instr jmp [$($n:tt)+]) => { $crate::interpreter::Instruction::Jmp($($n)+) };
+    (@instr mul [$($n:tt)+]) => { $crate::program!(@op Mul $($n)+) };
+    (@instr jz [$($n:tt)+]) => { $crate::program!(@op JumpIfZero $($n)+) };
+    (@instr jnz [$($n:tt)+]) => { $crate::program!(@op JumpIfNotZero $($n)+) };
+    (@instr ldm [$($n:tt)+]) => { $crate::program!(@op LoadMemory $($n)+) };
+    (@instr stm [$($n:tt)+]) => { $crate::program!(@op StoreMemory $($n)+) };
+    (@instr tgl [$($n:tt)+]) => { $crate::program!(@op Toggle $($n)+) };
+    (@instr jmpa []) => { $crate::program!(@op JumpByAccumulator 0) };
+    (@instr hlt []) => { $crate::program!(@op Halt 0) };
+    (@instr snd []) => { $crate::program!(@op Snd 0) };
+    (@instr rcv []) => { $crate::program!(@op Rcv 0) };
+    (@instr ld [$r:ident]) => { $crate::program!(@register Load $r) };
+    (@instr st [$r:ident]) => { $crate::program!(@register Store $r) };
+    (@instr addr [$r:ident]) => { $crate::program!(@register AddRegister $r) };
+    (@instr subr [$r:ident]) => { $crate::program!(@register SubRegister $r) };
+    (@instr mulr [$r:ident]) => { $crate::program!(@register MulRegister $r) };
+    (@instr ldi [$r:ident]) => { $crate::program!(@register LoadIndirect $r) };
+    (@instr sti [$r:ident]) => { $crate::program!(@register StoreIndirect $r) };
+    (@instr $m:ident [$($n:tt)*]) => {
+        compile_error!(concat!("invalid instruction ", stringify!($m $($n)*)))
+    };
+
+    (@op $op:ident $($n:tt)+) => {
+        $crate::interpreter::Instruction::Custom(&$crate::interpreter::$op, $($n)+)
+    };
+    (@register $op:ident $r:ident) => {{
+        const REGISTER: i64 = match $crate::interpreter::encode_register(stringify!($r)) {
+            Some(register) => register,
+            None => panic!(concat!("invalid register name ", stringify!($r))),
+        };
+        $crate::interpreter::Instruction::Custom(&$crate::interpreter::$op, REGISTER)
+    }};
+
+    ($($t:tt)*) => {
+        $crate::program!(@munch [] $($t)* ;)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::{Halt, InstructionSet, Mul, VirtualMachine};
+
+    use super::*;
+
+    #[test]
+    fn builds_programs() {
+        let p = Program::builder()
+            .nop(1)
+            .acc(2)
+            .op(&Mul, -3)
+            .jmp(2)
+            .instruction(Instruction::Acc(100))
+            .op(&Halt, 0)
+            .build();
+        assert_eq!(
+            "nop +1\nacc +2\nmul -3\njmp +2\nacc +100\nhlt\n",
+            p.to_string()
+        );
+        assert_eq!(Ok(-6), VirtualMachine::new(&p).execute());
+    }
+
+    #[test]
+    fn macro_matches_parser() {
+        let lines = [
+            "nop +1", "acc -2", "jmp +0", "mul +3", "jz -1", "jnz +2", "jmpa", "hlt", "snd", "rcv",
+            "ld a", "st b", "addr c", "subr d", "mulr e", "ldm +5", "stm -5", "ldi p", "sti q",
+            "tgl -2",
+        ];
+        let parsed = Program::parse_lines_with(&lines, &InstructionSet::extended()).unwrap();
+        let built = program! {
+            nop +1; acc -2; jmp 0; mul 3; jz -1; jnz +2; jmpa; hlt; snd; rcv;
+            ld a; st b; addr c; subr d; mulr e; ldm +5; stm -5; ldi p; sti q; tgl -2;
+        };
+        assert_eq!(parsed.instructions(), built.instructions());
+    }
+
+    #[test]
+    fn macro_operands() {
+        const N: i64 = 4;
+        let p = program! { acc N; acc (N * 2); acc -N; nop (-1) };
+        assert_eq!(
+            &[
+                Instruction::Acc(4),
+                Instruction::Acc(8),
+                Instruction::Acc(-4),
+                Instruction::Nop(-1)
+            ],
+            p.instructions()
+        );
+        assert_eq!(0, program! {}.len());
+    }
+}
@@ -0,0 +1,345 @@
+use std::{convert::TryFrom, fs, path::Path};
+
+use super::{BytecodeError, CodeParseError, CodeParseResult, Instruction, InstructionSet, Program};
+
+const MAGIC: &[u8; 4] = b"HHBC";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = MAGIC.len() + 1;
+const CHECKSUM_LEN: usize = 4;
+
+const OP_NOP: u64 = 0;
+const OP_ACC: u64 = 1;
+const OP_JMP: u64 = 2;
+const OP_CUSTOM: u64 = 3;
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for x in bytes {
+        a = (a + *x as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn write_signed(out: &mut Vec<u8>, value: i64) {
+    write_varint(out, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn error(&self, error: BytecodeError) -> CodeParseError {
+        CodeParseError::InvalidBytecode {
+            offset: self.at,
+            error,
+        }
+    }
+
+    fn byte(&mut self) -> CodeParseResult<u8> {
+        let b = *self
+            .bytes
+            .get(self.at)
+            .ok_or_else(|| self.error(BytecodeError::UnexpectedEnd))?;
+        self.at += 1;
+        Ok(b)
+    }
+
+    /// Only accepts the shortest encoding of a value that fits in a `u64`, as `write_varint`
+    /// produces it.
+    fn varint(&mut self) -> CodeParseResult<u64> {
+        let start = self.at;
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let b = self.byte()?;
+            let payload = (b & 0x7f) as u64;
+            // The tenth byte only has room for the top bit, and a last byte of zero adds nothing.
+            let overflows = payload << shift >> shift != payload;
+            let overlong = b == 0 && shift > 0;
+            if overflows || overlong {
+                break;
+            }
+            value |= payload << shift;
+            if b & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        self.at = start;
+        Err(self.error(BytecodeError::InvalidVarint))
+    }
+
+    fn signed(&mut self) -> CodeParseResult<i64> {
+        let v = self.varint()?;
+        Ok((v >> 1) as i64 ^ -((v & 1) as i64))
+    }
+
+    fn offset(&mut self) -> CodeParseResult<isize> {
+        let start = self.at;
+        let v = self.signed()?;
+        isize::try_from(v).map_err(|_| {
+            self.at = start;
+            self.error(BytecodeError::OperandOutOfRange(v))
+        })
+    }
+
+    fn count(&mut self) -> CodeParseResult<usize> {
+        let start = self.at;
+        let v = self.varint()?;
+        // Every entry takes at least one byte, which bounds what a valid file can claim.
+        match usize::try_from(v) {
+            Ok(v) if v <= self.bytes.len() - self.at => Ok(v),
+            _ => {
+                self.at = start;
+                Err(self.error(BytecodeError::UnexpectedEnd))
+            }
+        }
+    }
+
+    fn string(&mut self) -> CodeParseResult<&'a str> {
+        let len = self.count()?;
+        let start = self.at;
+        let bytes = self
+            .bytes
+            .get(start..start + len)
+            .ok_or_else(|| self.error(BytecodeError::UnexpectedEnd))?;
+        let s = std::str::from_utf8(bytes).map_err(|_| self.error(BytecodeError::InvalidUtf8))?;
+        self.at += len;
+        Ok(s)
+    }
+}
+
+impl Program {
+    /// Encodes the program as `HHBC`, a version byte, a table of custom mnemonics, the
+    /// instructions (opcode byte and zig-zag varint operand) and an Adler-32 checksum.
+    pub fn to_bytecode(&self) -> Vec<u8> {
+        let mut custom: Vec<&str> = Vec::new();
+        for instr in self.instructions() {
+            if let Instruction::Custom(op, _) = instr {
+                if !custom.contains(&op.mnemonic()) {
+                    custom.push(op.mnemonic());
+                }
+            }
+        }
+
+        let mut out = MAGIC.to_vec();
+        out.push(VERSION);
+        write_varint(&mut out, custom.len() as u64);
+        for m in &custom {
+            write_varint(&mut out, m.len() as u64);
+            out.extend_from_slice(m.as_bytes());
+        }
+        write_varint(&mut out, self.len() as u64);
+        for instr in self.instructions() {
+            let (opcode, operand) = match instr {
+                Instruction::Nop(i) => (OP_NOP, *i as i64),
+                Instruction::Acc(i) => (OP_ACC, *i),
+                Instruction::Jmp(i) => (OP_JMP, *i as i64),
+                Instruction::Custom(op, arg) => {
+                    let index = custom.iter().position(|m| *m == op.mnemonic()).unwrap();
+                    (OP_CUSTOM + index as u64, *arg)
+                }
+            };
+            write_varint(&mut out, opcode);
+            write_signed(&mut out, operand);
+        }
+        let checksum = adler32(&out);
+        out.extend_from_slice(&checksum.to_le_bytes());
+        out
+    }
+
+    pub fn from_bytecode(bytes: &[u8]) -> CodeParseResult<Self> {
+        Self::from_bytecode_with(bytes, &InstructionSet::new())
+    }
+
+    pub fn from_bytecode_with(bytes: &[u8], set: &InstructionSet) -> CodeParseResult<Self> {
+        let mut reader = Reader { bytes, at: 0 };
+        if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(reader.error(BytecodeError::InvalidMagic));
+        }
+        reader.at = MAGIC.len();
+        let version = reader.byte()?;
+        if version != VERSION {
+            reader.at = MAGIC.len();
+            return Err(reader.error(BytecodeError::UnsupportedVersion(version)));
+        }
+        if bytes.len() < HEADER_LEN + CHECKSUM_LEN {
+            reader.at = bytes.len();
+            return Err(reader.error(BytecodeError::UnexpectedEnd));
+        }
+        let (body, checksum) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+        let mut expected = [0; CHECKSUM_LEN];
+        expected.copy_from_slice(checksum);
+        if adler32(body) != u32::from_le_bytes(expected) {
+            reader.at = body.len();
+            return Err(reader.error(BytecodeError::ChecksumMismatch));
+        }
+        reader.bytes = body;
+
+        let mut custom = Vec::new();
+        for _ in 0..reader.count()? {
+            let start = reader.at;
+            let mnemonic = reader.string()?;
+            match set.get(mnemonic) {
+                Some(op) => custom.push(op),
+                None => {
+                    reader.at = start;
+                    return Err(
+                        reader.error(BytecodeError::UnknownInstruction(mnemonic.to_string()))
+                    );
+                }
+            }
+        }
+
+        let len = reader.count()?;
+        let mut statements = Vec::with_capacity(len);
+        for _ in 0..len {
+            let start = reader.at;
+            let opcode = reader.varint()?;
+            let instr = match opcode {
+                OP_NOP => Instruction::Nop(reader.offset()?),
+                OP_ACC => Instruction::Acc(reader.signed()?),
+                OP_JMP => Instruction::Jmp(reader.offset()?),
+                _ => match custom.get((opcode - OP_CUSTOM) as usize) {
+                    Some(op) => Instruction::Custom(*op, reader.signed()?),
+                    None => {
+                        reader.at = start;
+                        return Err(reader.error(BytecodeError::InvalidOpcode(opcode)));
+                    }
+                },
+            };
+            statements.push(instr);
+        }
+        if reader.at != body.len() {
+            return Err(reader.error(BytecodeError::TrailingBytes));
+        }
+        Ok(Program::from(statements))
+    }
+
+    pub fn load_bytecode_file<P: AsRef<Path> + Copy>(path: P) -> CodeParseResult<Self> {
+        Self::load_bytecode_file_with(path, &InstructionSet::new())
+    }
+
+    pub fn load_bytecode_file_with<P: AsRef<Path> + Copy>(
+        path: P,
+        set: &InstructionSet,
+    ) -> CodeParseResult<Self> {
+        let bytes = fs::read(path).map_err(|e| CodeParseError::from_io(path, e))?;
+        Self::from_bytecode_with(&bytes, set)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(bytes: &[u8]) -> (usize, BytecodeError) {
+        match Program::from_bytecode(bytes) {
+            Err(CodeParseError::InvalidBytecode { offset, error }) => (offset, error),
+            r => panic!("unexpected result {:?}", r.map(|p| p.len())),
+        }
+    }
+
+    fn with_checksum(mut bytes: Vec<u8>) -> Vec<u8> {
+        let checksum = adler32(&bytes);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn encodes_compactly() {
+        let p = Program::parse_lines(&["nop +0", "acc -1", "jmp +64"]).unwrap();
+        assert_eq!(
+            with_checksum(vec![
+                b'H', b'H', b'B', b'C', 1, 0, 3, 0, 0, 1, 1, 2, 0x80, 0x01
+            ]),
+            p.to_bytecode()
+        );
+    }
+
+    #[test]
+    fn round_trips_extreme_operands() {
+        let mut p = Program::parse_lines(&["acc +0"]).unwrap();
+        for &v in &[i64::MIN, i64::MAX, -1, 63, -64] {
+            p.set_instr(0, Instruction::Acc(v));
+            let q = Program::from_bytecode(&p.to_bytecode()).unwrap();
+            assert_eq!(p.instructions(), q.instructions());
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let text = std::fs::read_to_string("./inputs/day08.txt").unwrap();
+        let lines: Vec<_> = text.lines().collect();
+        let p = Program::parse_lines(&lines).unwrap();
+        let q = Program::from_bytecode(&p.to_bytecode()).unwrap();
+        assert_eq!(text, q.to_string());
+
+        let set = InstructionSet::extended();
+        let p = Program::parse_lines_with(&["mul -3", "hlt", "acc +1", "mul +2"], &set).unwrap();
+        let bytes = p.to_bytecode();
+        let q = Program::from_bytecode_with(&bytes, &set).unwrap();
+        assert_eq!(p.instructions(), q.instructions());
+        assert!(matches!(
+            error(&bytes),
+            (6, BytecodeError::UnknownInstruction(m)) if m == "mul"
+        ));
+    }
+
+    #[test]
+    fn reports_corruption() {
+        assert_eq!((0, BytecodeError::InvalidMagic), error(b"HHB"));
+        assert_eq!((0, BytecodeError::InvalidMagic), error(b"ABCD\x01"));
+        assert_eq!(
+            (4, BytecodeError::UnsupportedVersion(7)),
+            error(b"HHBC\x07")
+        );
+        assert_eq!((5, BytecodeError::UnexpectedEnd), error(b"HHBC\x01"));
+
+        let mut bytes = Program::parse_lines(&["acc +5"]).unwrap().to_bytecode();
+        bytes[8] ^= 1;
+        assert_eq!((9, BytecodeError::ChecksumMismatch), error(&bytes));
+
+        let bytes = with_checksum(b"HHBC\x01\x00\x02\x00\x00".to_vec());
+        assert_eq!((9, BytecodeError::UnexpectedEnd), error(&bytes));
+        let bytes = with_checksum(b"HHBC\x01\x00\x01\x09\x00".to_vec());
+        assert_eq!((7, BytecodeError::InvalidOpcode(9)), error(&bytes));
+        let bytes = with_checksum(b"HHBC\x01\x00\x01\x00\x00\x00".to_vec());
+        assert_eq!((9, BytecodeError::TrailingBytes), error(&bytes));
+        let bytes = with_checksum(b"HHBC\x01\x00\x01\x01\x80".to_vec());
+        assert_eq!((9, BytecodeError::UnexpectedEnd), error(&bytes));
+        let bytes = with_checksum(b"HHBC\x01\x00\x01\x01\x80\x00".to_vec());
+        assert_eq!((8, BytecodeError::InvalidVarint), error(&bytes));
+        let mut bytes = b"HHBC\x01\x00\x01\x01".to_vec();
+        bytes.extend_from_slice(&[0xff; 9]);
+        bytes.push(0x02);
+        assert_eq!(
+            (8, BytecodeError::InvalidVarint),
+            error(&with_checksum(bytes))
+        );
+        let mut bytes = b"HHBC\x01\x00\x01\x01".to_vec();
+        bytes.extend_from_slice(&[0xff; 10]);
+        bytes.push(0x01);
+        assert_eq!(
+            (8, BytecodeError::InvalidVarint),
+            error(&with_checksum(bytes))
+        );
+        let bytes = with_checksum(b"HHBC\x01\x01\x02\xff\xfe".to_vec());
+        assert_eq!((7, BytecodeError::InvalidUtf8), error(&bytes));
+    }
+
+    #[test]
+    fn load_missing_file() {
+        let p = Program::load_bytecode_file("this file does not exist.hhbc");
+        assert!(matches!(p, Err(CodeParseError::IOError { .. })))
+    }
+}
@@ -0,0 +1,153 @@
+use std::collections::VecDeque;
+
+use super::{
+    parse_no_operand, ExecutionError, ExecutionResult, Flow, Operation, ParseResult, ProgramState,
+};
+
+/// Where `snd` and `rcv` exchange values with the outside world.
+pub trait Channel {
+    fn send(&mut self, value: i64);
+
+    fn receive(&mut self) -> Option<i64>;
+}
+
+/// A pair of queues: values waiting to be received by the machine and values it has sent.
+#[derive(Debug, Default, Clone)]
+pub struct Mailbox {
+    pub inbox: VecDeque<i64>,
+    pub outbox: VecDeque<i64>,
+}
+
+impl Channel for Mailbox {
+    fn send(&mut self, value: i64) {
+        self.outbox.push_back(value);
+    }
+
+    fn receive(&mut self) -> Option<i64> {
+        self.inbox.pop_front()
+    }
+}
+
+impl Mailbox {
+    /// A channel that only looks at the inbox and holds sent values back, so that a step can
+    /// still be rejected without losing or leaking any values.
+    pub(super) fn tentative(&self) -> Tentative<'_> {
+        Tentative {
+            inbox: &self.inbox,
+            traffic: Traffic::default(),
+        }
+    }
+
+    /// Carries out the traffic of a step that has been accepted.
+    pub(super) fn commit(&mut self, traffic: Traffic) {
+        self.inbox.drain(..traffic.received);
+        self.outbox.extend(traffic.sent);
+    }
+}
+
+/// What a step did to a `Mailbox`, see `Mailbox::tentative`.
+#[derive(Debug, Default)]
+pub(super) struct Traffic {
+    received: usize,
+    sent: Vec<i64>,
+}
+
+pub(super) struct Tentative<'m> {
+    inbox: &'m VecDeque<i64>,
+    traffic: Traffic,
+}
+
+impl Tentative<'_> {
+    pub(super) fn into_traffic(self) -> Traffic {
+        self.traffic
+    }
+}
+
+impl Channel for Tentative<'_> {
+    fn send(&mut self, value: i64) {
+        self.traffic.sent.push(value);
+    }
+
+    fn receive(&mut self) -> Option<i64> {
+        let value = self.inbox.get(self.traffic.received).copied();
+        if value.is_some() {
+            self.traffic.received += 1;
+        }
+        value
+    }
+}
+
+/// Sends the accumulator. Without a channel the value is dropped.
+pub struct Snd;
+
+impl Operation for Snd {
+    fn mnemonic(&self) -> &str {
+        "snd"
+    }
+
+    fn parse_operand(&self, s: &str) -> ParseResult<i64> {
+        parse_no_operand(self.mnemonic(), s)
+    }
+
+    fn format_operand(&self, _: i64) -> Option<String> {
+        None
+    }
+
+    fn successors(&self, _: i64) -> Option<Vec<Flow>> {
+        Some(vec![Flow::Next])
+    }
+
+    fn execute(&self, _: i64, _: &mut ProgramState) -> ExecutionResult<Flow> {
+        Ok(Flow::Next)
+    }
+
+    fn execute_with(
+        &self,
+        _: i64,
+        state: &mut ProgramState,
+        channel: &mut dyn Channel,
+    ) -> ExecutionResult<Flow> {
+        channel.send(state.accumulator());
+        Ok(Flow::Next)
+    }
+}
+
+/// Replaces the accumulator with a received value, blocking until there is one.
+pub struct Rcv;
+
+impl Operation for Rcv {
+    fn mnemonic(&self) -> &str {
+        "rcv"
+    }
+
+    fn parse_operand(&self, s: &str) -> ParseResult<i64> {
+        parse_no_operand(self.mnemonic(), s)
+    }
+
+    fn format_operand(&self, _: i64) -> Option<String> {
+        None
+    }
+
+    fn successors(&self, _: i64) -> Option<Vec<Flow>> {
+        Some(vec![Flow::Next])
+    }
+
+    fn execute(&self, _: i64, state: &mut ProgramState) -> ExecutionResult<Flow> {
+        Err(ExecutionError::Blocked(state.instruction))
+    }
+
+    fn execute_with(
+        &self,
+        _: i64,
+        state: &mut ProgramState,
+        channel: &mut dyn Channel,
+    ) -> ExecutionResult<Flow> {
+        match channel.receive() {
+            Some(value) => {
+                state.set_accumulator(value);
+                Ok(Flow::Next)
+            }
+            None => Err(ExecutionError::Blocked(state.instruction)),
+        }
+    }
+}
@@ -0,0 +1,389 @@
+use std::{collections::HashSet, mem, time::Instant};
+
+use super::{
+    ExecutionError, ExecutionLimits, ExecutionResult, Instruction, LoopDetection, Machine, Mailbox,
+    Operation, Position, Program, ProgramState, Traffic,
+};
+
+/// How often the wall-clock budget is checked; reading the clock on every step would cost more
+/// than the step itself. A power of two, so that the check is a mask.
+const CLOCK_INTERVAL: u64 = 1024;
+
+/// How to take back a step that loop detection rejects.
+enum Undo {
+    /// Builtins only move and add to the accumulator.
+    Rewind(Position),
+    /// Custom operations replace the whole state, and their mailbox traffic is only carried out
+    /// once the step is accepted.
+    Restore(ProgramState, Traffic),
+}
+
+/// One instruction with its operands and jump target resolved, together with the handler that
+/// runs it. The handler is picked once when compiling instead of matching on every step.
+struct Op {
+    run: fn(&Op, &mut ProgramState, &Mailbox, usize) -> ExecutionResult<Undo>,
+    at: usize,
+    target: isize,
+    acc: i64,
+    custom: Option<(&'static dyn Operation, i64)>,
+}
+
+fn jump(op: &Op, state: &mut ProgramState, _: &Mailbox, _: usize) -> ExecutionResult<Undo> {
+    let position = state.position();
+    state.instruction = op.target as usize;
+    Ok(Undo::Rewind(position))
+}
+
+fn add_and_jump(op: &Op, state: &mut ProgramState, _: &Mailbox, _: usize) -> ExecutionResult<Undo> {
+    let position = state.position();
+    state.add_to_accumulator(op.acc)?;
+    state.instruction = op.target as usize;
+    Ok(Undo::Rewind(position))
+}
+
+fn fault(op: &Op, _: &mut ProgramState, _: &Mailbox, _: usize) -> ExecutionResult<Undo> {
+    Err(ExecutionError::InvalidAccess(op.target))
+}
+
+fn read_only(op: &Op, _: &mut ProgramState, _: &Mailbox, _: usize) -> ExecutionResult<Undo> {
+    Err(ExecutionError::ReadOnlyProgram(op.at))
+}
+
+fn custom(
+    op: &Op,
+    state: &mut ProgramState,
+    mailbox: &Mailbox,
+    len: usize,
+) -> ExecutionResult<Undo> {
+    let (operation, operand) = op.custom.expect("compiled as a custom operation");
+    let mut channel = mailbox.tentative();
+    let next = state.apply(operation, operand, len, &mut channel)?;
+    let before = mem::replace(state, next);
+    Ok(Undo::Restore(before, channel.into_traffic()))
+}
+
+impl Op {
+    fn compile(at: usize, instr: Instruction, len: usize) -> Self {
+        let mut op = Op {
+            run: jump,
+            at,
+            target: 0,
+            acc: 0,
+            custom: None,
+        };
+        let offset = match instr {
+            Instruction::Nop(_) => 1,
+            Instruction::Acc(i) => {
+                op.acc = i;
+                1
+            }
+            Instruction::Jmp(i) => i,
+            Instruction::Custom(operation, operand) => {
+                op.run = if operation.modifies_program() {
+                    read_only
+                } else {
+                    custom
+                };
+                op.custom = Some((operation, operand));
+                return op;
+            }
+        };
+        op.target = (at as isize).saturating_add(offset);
+        op.run = if op.target < 0 || op.target as usize > len {
+            fault
+        } else if op.acc != 0 {
+            add_and_jump
+        } else {
+            jump
+        };
+        op
+    }
+}
+
+/// A program compiled into threaded code, with all jump targets resolved and validated up
+/// front, for use with `CompiledMachine`.
+pub struct CompiledProgram {
+    ops: Vec<Op>,
+}
+
+impl CompiledProgram {
+    pub fn new(program: &Program) -> Self {
+        let len = program.len();
+        let ops = program
+            .instructions()
+            .iter()
+            .enumerate()
+            .map(|(at, instr)| Op::compile(at, *instr, len))
+            .collect();
+        CompiledProgram { ops }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+/// A faster alternative to `VirtualMachine` that runs a `CompiledProgram` and tracks visited
+/// addresses in a dense bitmap. It gives the same results and exchanges values through its own
+/// mailbox, but has no observers or history.
+pub struct CompiledMachine<'a> {
+    program: &'a CompiledProgram,
+    state: ProgramState,
+    limits: ExecutionLimits,
+    visited: Vec<bool>,
+    seen_states: HashSet<ProgramState>,
+    steps: u64,
+    started: Option<Instant>,
+    mailbox: Mailbox,
+}
+
+impl<'a> CompiledMachine<'a> {
+    pub fn new(program: &'a CompiledProgram) -> Self {
+        Self::with_limits(program, ExecutionLimits::new())
+    }
+
+    pub fn with_limits(program: &'a CompiledProgram, limits: ExecutionLimits) -> Self {
+        Self::with_state(program, limits, ProgramState::default())
+    }
+
+    /// Starts from `state` instead of the default, for example `MachineProfile::initial_state`.
+    pub fn with_state(
+        program: &'a CompiledProgram,
+        limits: ExecutionLimits,
+        state: ProgramState,
+    ) -> Self {
+        let mut machine = CompiledMachine {
+            program,
+            state,
+            limits,
+            visited: vec![false; program.len() + 1],
+            seen_states: HashSet::new(),
+            steps: 0,
+            started: None,
+            mailbox: Mailbox::default(),
+        };
+        machine.record();
+        machine
+    }
+
+    /// Queues a value for `rcv`.
+    pub fn send(&mut self, value: i64) {
+        self.mailbox.inbox.push_back(value);
+    }
+
+    /// Takes the oldest value sent with `snd`.
+    pub fn receive(&mut self) -> Option<i64> {
+        self.mailbox.outbox.pop_front()
+    }
+
+    pub fn mailbox(&self) -> &Mailbox {
+        &self.mailbox
+    }
+
+    fn check_limits(&mut self) -> ExecutionResult<()> {
+        if let Some(max) = self.limits.max_steps {
+            if self.steps >= max {
+                return Err(ExecutionError::StepLimitExceeded(max));
+            }
+        }
+        if let Some(budget) = self.limits.time_budget {
+            if self.steps & (CLOCK_INTERVAL - 1) == 0
+                && self.started.get_or_insert_with(Instant::now).elapsed() > budget
+            {
+                return Err(ExecutionError::TimeLimitExceeded(budget));
+            }
+        }
+        Ok(())
+    }
+
+    fn record(&mut self) -> bool {
+        let state = &self.state;
+        match self.limits.loop_detection {
+            LoopDetection::Disabled => true,
+            LoopDetection::Address => match self.visited.get_mut(state.instruction) {
+                Some(visited) => !mem::replace(visited, true),
+                None => true,
+            },
+            LoopDetection::State => self.seen_states.insert(state.clone()),
+        }
+    }
+}
+
+impl Machine for CompiledMachine<'_> {
+    fn execute_one(&mut self) -> ExecutionResult<()> {
+        if self.terminated() {
+            return Ok(());
+        }
+        self.check_limits()?;
+        let at = self.state.instruction;
+        let op = self
+            .program
+            .ops
+            .get(at)
+            .ok_or(ExecutionError::InvalidAccess(at as isize))?;
+        let undo = (op.run)(op, &mut self.state, &self.mailbox, self.program.len())?;
+        if !self.record() {
+            let error = match self.limits.loop_detection {
+                LoopDetection::State => {
+                    ExecutionError::RepeatedState(self.state.instruction, self.state.accumulator())
+                }
+                _ => ExecutionError::InfiniteLoop(self.state.instruction),
+            };
+            match undo {
+                Undo::Rewind(position) => self.state.rewind(position),
+                Undo::Restore(state, _) => self.state = state,
+            }
+            return Err(error);
+        }
+        if let Undo::Restore(_, traffic) = undo {
+            self.mailbox.commit(traffic);
+        }
+        self.steps += 1;
+        Ok(())
+    }
+
+    fn terminated(&self) -> bool {
+        self.state.instruction == self.program.len()
+    }
+
+    fn current_state(&self) -> &ProgramState {
+        &self.state
+    }
+
+    fn steps(&self) -> u64 {
+        self.steps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{Arithmetic, InstructionSet, MachineProfile, VirtualMachine};
+
+    fn run<M: Machine>(machine: &mut M) -> (ExecutionResult<i64>, ProgramState, u64) {
+        let result = machine.execute();
+        (result, machine.current_state().clone(), machine.steps())
+    }
+
+    #[test]
+    fn runs_programs() {
+        let p = Program::parse_lines(&["nop +0", "acc +1", "jmp +2", "acc +5", "acc -3"]).unwrap();
+        let c = CompiledProgram::new(&p);
+        assert_eq!(Ok(-2), CompiledMachine::new(&c).execute());
+
+        let p = Program::parse_lines(&["acc +1", "jmp -1"]).unwrap();
+        let c = CompiledProgram::new(&p);
+        assert_eq!(
+            Err(ExecutionError::InfiniteLoop(0)),
+            CompiledMachine::new(&c).execute()
+        );
+
+        let p = Program::parse_lines(&["nop +0", "jmp -2"]).unwrap();
+        let c = CompiledProgram::new(&p);
+        let mut m = CompiledMachine::new(&c);
+        assert_eq!(Err(ExecutionError::InvalidAccess(-1)), m.execute());
+        assert_eq!(1, m.steps());
+    }
+
+    #[test]
+    fn starts_from_state() {
+        let p = Program::parse_lines(&["acc +1", "acc +2"]).unwrap();
+        let c = CompiledProgram::new(&p);
+        let state = ProgramState {
+            instruction: 1,
+            accumulator: 5,
+            ..ProgramState::default()
+        };
+        let mut m = CompiledMachine::with_state(&c, ExecutionLimits::new(), state);
+        assert_eq!(Ok(7), m.execute());
+        assert_eq!(1, m.steps());
+
+        let state = ProgramState {
+            instruction: 3,
+            ..ProgramState::default()
+        };
+        let mut m = CompiledMachine::with_state(&c, ExecutionLimits::new(), state);
+        assert_eq!(Err(ExecutionError::InvalidAccess(3)), m.execute());
+    }
+
+    #[test]
+    fn exchanges_values() {
+        let set = InstructionSet::extended();
+        let p = Program::parse_lines_with(&["rcv", "mul +2", "snd", "rcv"], &set).unwrap();
+        let c = CompiledProgram::new(&p);
+        let mut m = CompiledMachine::new(&c);
+        m.send(21);
+        assert_eq!(Err(ExecutionError::Blocked(3)), m.execute());
+        assert_eq!(Some(42), m.receive());
+        m.send(5);
+        assert_eq!(Ok(5), m.execute());
+    }
+
+    #[test]
+    fn matches_interpreter() {
+        let set = InstructionSet::extended();
+        let mnemonics = [
+            "nop", "acc", "jmp", "mul", "jz", "jnz", "jmpa", "hlt", "snd", "rcv", "ld", "st",
+            "addr", "ldm", "stm",
+        ];
+        let limits = [
+            ExecutionLimits::new(),
+            ExecutionLimits::new()
+                .loop_detection(LoopDetection::State)
+                .max_steps(200),
+            ExecutionLimits::unlimited().max_steps(50),
+            ExecutionLimits::new().max_steps(5),
+        ];
+        let storage = MachineProfile::new().register("a").register("b").memory(3);
+        let profiles = [
+            MachineProfile::new(),
+            storage.clone(),
+            storage.arithmetic(Arithmetic::Wrapping),
+        ];
+        let mut seed: u64 = 0x2020;
+        let mut next = |m: u64| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) % m
+        };
+        for _ in 0..1000 {
+            let len = next(15) as usize;
+            let lines: Vec<String> = (0..len)
+                .map(|_| match mnemonics[next(mnemonics.len() as u64) as usize] {
+                    m @ "jmpa" | m @ "hlt" | m @ "snd" | m @ "rcv" => m.to_string(),
+                    // Keep products small so that long runs cannot overflow.
+                    "mul" => format!("mul {:+}", next(3) as i64 - 1),
+                    m @ "ld" | m @ "st" | m @ "addr" => {
+                        format!("{} {}", m, ["a", "b"][next(2) as usize])
+                    }
+                    m @ "ldm" | m @ "stm" => format!("{} {:+}", m, next(5) as i64 - 1),
+                    m => format!("{} {:+}", m, next(11) as i64 - 5),
+                })
+                .collect();
+            let lines: Vec<&str> = lines.iter().map(String::as_ref).collect();
+            let p = Program::parse_lines_with(&lines, &set).unwrap();
+            let c = CompiledProgram::new(&p);
+            for l in &limits {
+                for profile in &profiles {
+                    let mut vm = VirtualMachine::with_state(&p, *l, profile.initial_state());
+                    let mut cm = CompiledMachine::with_state(&c, *l, profile.initial_state());
+                    vm.send(7);
+                    cm.send(7);
+                    assert_eq!(
+                        (run(&mut vm), &vm.mailbox().inbox, &vm.mailbox().outbox),
+                        (run(&mut cm), &cm.mailbox().inbox, &cm.mailbox().outbox),
+                        "{:?} {:?} {:?}",
+                        lines,
+                        l,
+                        profile
+                    );
+                }
+            }
+        }
+    }
+}
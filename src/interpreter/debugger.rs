@@ -0,0 +1,631 @@
+use std::{
+    fmt::{self, Write as _},
+    io::{self, BufRead, Write},
+    str::FromStr,
+};
+
+use super::{CommandError, CommandResult, ExecutionError, Program, VirtualMachine};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    pub fn holds(&self, a: i64, b: i64) -> bool {
+        match self {
+            Self::Eq => a == b,
+            Self::Ne => a != b,
+            Self::Lt => a < b,
+            Self::Le => a <= b,
+            Self::Gt => a > b,
+            Self::Ge => a >= b,
+        }
+    }
+}
+
+impl FromStr for Comparison {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "==" => Ok(Self::Eq),
+            "!=" => Ok(Self::Ne),
+            "<" => Ok(Self::Lt),
+            "<=" => Ok(Self::Le),
+            ">" => Ok(Self::Gt),
+            ">=" => Ok(Self::Ge),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Eq => "==",
+            Self::Ne => "!=",
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Gt => ">",
+            Self::Ge => ">=",
+        })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Breakpoint {
+    Instruction(usize),
+    Accumulator(Comparison, i64),
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Instruction(i) => write!(f, "instruction {}", i),
+            Self::Accumulator(c, v) => write!(f, "acc {} {}", c, v),
+        }
+    }
+}
+
+enum Stop {
+    Stepped,
+    Breakpoint(usize),
+    Terminated,
+    Failed(ExecutionError),
+}
+
+const HELP: &str = "\
+step [n]         (s) execute n instructions (default 1)
+back [n]         (bk) undo n instructions (default 1)
+continue         (c) run until a breakpoint, termination or error
+break <n>        (b) stop before executing instruction n
+break acc <op> <v>   stop when the accumulator satisfies the condition (==, !=, <, <=, >, >=)
+delete <id>      (d) remove a breakpoint
+info             (i) list breakpoints
+print            (p) print the program state
+list [radius]    (l) show the listing around the current instruction
+reset            (r) restart the program, keeping breakpoints
+quit             (q) exit the debugger
+An empty line repeats the previous command.
+";
+
+pub struct Debugger<'a> {
+    program: &'a Program,
+    vm: VirtualMachine<'a>,
+    breakpoints: Vec<Option<Breakpoint>>,
+    last_command: Option<String>,
+    /// Nothing has run since the start or the last reset, so `continue` also checks the
+    /// breakpoints before the first instruction.
+    at_start: bool,
+}
+
+impl<'a> Debugger<'a> {
+    pub const PROMPT: &'static str = "(dbg) ";
+    /// How many steps `back` can undo.
+    pub const HISTORY: usize = 10_000;
+
+    pub fn new(program: &'a Program) -> Self {
+        let mut vm = VirtualMachine::new(program);
+        vm.keep_history(Self::HISTORY);
+        Debugger {
+            program,
+            vm,
+            breakpoints: Vec::new(),
+            last_command: None,
+            at_start: true,
+        }
+    }
+
+    pub fn vm(&self) -> &VirtualMachine<'a> {
+        &self.vm
+    }
+
+    pub fn add_breakpoint(&mut self, bp: Breakpoint) -> usize {
+        self.breakpoints.push(Some(bp));
+        self.breakpoints.len()
+    }
+
+    pub fn remove_breakpoint(&mut self, id: usize) -> Option<Breakpoint> {
+        self.breakpoints.get_mut(id.checked_sub(1)?)?.take()
+    }
+
+    /// Reads commands from `input` until it is exhausted or `quit` is entered. With `echo` set,
+    /// every command is written after the prompt, which makes transcripts of scripts readable.
+    pub fn run<R: BufRead, W: Write>(&mut self, input: R, out: W, echo: bool) -> io::Result<()> {
+        run_session(input, out, Self::PROMPT, echo, |line| self.execute(line))
+    }
+
+    /// Executes a single command and returns its output, or `None` if the debugger should exit.
+    pub fn execute(&mut self, line: &str) -> Option<CommandResult<String>> {
+        let line = match (line.trim(), &self.last_command) {
+            ("", Some(last)) => last.clone(),
+            (l, _) => l.to_string(),
+        };
+        if line.is_empty() {
+            return Some(Ok(String::new()));
+        }
+        self.last_command = Some(line.clone());
+
+        let mut parts = line.split_whitespace();
+        let command = parts.next().unwrap_or_default();
+        let args: Vec<_> = parts.collect();
+        Some(match command {
+            "s" | "step" => self.step(&args),
+            "bk" | "back" => self.back(&args),
+            "c" | "continue" => self.cont(&args),
+            "b" | "break" => self.set_breakpoint(&args),
+            "d" | "delete" => self.delete_breakpoint(&args),
+            "i" | "info" => self.info(&args),
+            "p" | "print" => self.print(&args),
+            "l" | "list" => self.list(&args),
+            "r" | "reset" => self.reset(&args),
+            "h" | "help" => Ok(HELP.to_string()),
+            "q" | "quit" => return None,
+            x => Err(CommandError::UnknownCommand(x.to_string())),
+        })
+    }
+
+    fn step(&mut self, args: &[&str]) -> CommandResult<String> {
+        let count = match args {
+            [] => 1,
+            [n] => parse_arg("step", n)?,
+            _ => return Err(CommandError::TooManyArguments("step".to_string())),
+        };
+        let mut stop = Stop::Stepped;
+        for _ in 0..count {
+            stop = self.advance(false);
+            if !matches!(stop, Stop::Stepped) {
+                break;
+            }
+        }
+        Ok(self.report(stop))
+    }
+
+    fn back(&mut self, args: &[&str]) -> CommandResult<String> {
+        let count = match args {
+            [] => 1,
+            [n] => parse_arg("back", n)?,
+            _ => return Err(CommandError::TooManyArguments("back".to_string())),
+        };
+        let exhausted = (0..count).any(|_| !self.vm.step_back());
+        let current = self.listing_line(self.vm.current_state().instruction);
+        if exhausted {
+            Ok(format!("Reached the start of the history\n{}\n", current))
+        } else {
+            Ok(format!("{}\n", current))
+        }
+    }
+
+    fn cont(&mut self, args: &[&str]) -> CommandResult<String> {
+        no_args("continue", args)?;
+        if std::mem::replace(&mut self.at_start, false) {
+            if let Some(id) = self.hit_breakpoint() {
+                return Ok(self.report(Stop::Breakpoint(id)));
+            }
+        }
+        loop {
+            let stop = self.advance(true);
+            if !matches!(stop, Stop::Stepped) {
+                return Ok(self.report(stop));
+            }
+        }
+    }
+
+    fn set_breakpoint(&mut self, args: &[&str]) -> CommandResult<String> {
+        let bp = match args {
+            ["acc", op, v] => {
+                Breakpoint::Accumulator(parse_arg("break", op)?, parse_arg("break", v)?)
+            }
+            [n] => Breakpoint::Instruction(parse_arg("break", n)?),
+            [] => return Err(CommandError::MissingArgument("break".to_string())),
+            _ => return Err(CommandError::TooManyArguments("break".to_string())),
+        };
+        let id = self.add_breakpoint(bp);
+        Ok(format!("Breakpoint {} at {}\n", id, bp))
+    }
+
+    fn delete_breakpoint(&mut self, args: &[&str]) -> CommandResult<String> {
+        let id = match args {
+            [id] => parse_arg("delete", id)?,
+            [] => return Err(CommandError::MissingArgument("delete".to_string())),
+            _ => return Err(CommandError::TooManyArguments("delete".to_string())),
+        };
+        match self.remove_breakpoint(id) {
+            Some(bp) => Ok(format!("Deleted breakpoint {} at {}\n", id, bp)),
+            None => Err(CommandError::InvalidArgument(
+                "delete".to_string(),
+                id.to_string(),
+            )),
+        }
+    }
+
+    fn info(&self, args: &[&str]) -> CommandResult<String> {
+        no_args("info", args)?;
+        let mut s = String::new();
+        for (i, bp) in self.breakpoints.iter().enumerate() {
+            if let Some(bp) = bp {
+                writeln!(s, "{:>3}: {}", i + 1, bp).unwrap();
+            }
+        }
+        if s.is_empty() {
+            s.push_str("No breakpoints\n");
+        }
+        Ok(s)
+    }
+
+    fn print(&self, args: &[&str]) -> CommandResult<String> {
+        no_args("print", args)?;
+        let state = self.vm.current_state();
+        let mut s = format!(
+            "instruction: {}{}\naccumulator: {}\n",
+            state.instruction,
+            if self.vm.terminated() {
+                " (terminated)"
+            } else {
+                ""
+            },
+            state.accumulator()
+        );
+        for (name, value) in state.registers.iter() {
+            writeln!(s, "{}: {}", name, value).unwrap();
+        }
+        for (address, value) in state.memory.iter() {
+            writeln!(s, "[{}]: {}", address, value).unwrap();
+        }
+        Ok(s)
+    }
+
+    fn list(&self, args: &[&str]) -> CommandResult<String> {
+        let radius = match args {
+            [] => 3,
+            [n] => parse_arg("list", n)?,
+            _ => return Err(CommandError::TooManyArguments("list".to_string())),
+        };
+        let current = self.vm.current_state().instruction;
+        let from = current.saturating_sub(radius);
+        let to = current.saturating_add(radius).min(self.program.len());
+        let mut s = String::new();
+        for i in from..=to {
+            writeln!(s, "{}", self.listing_line(i)).unwrap();
+        }
+        Ok(s)
+    }
+
+    fn reset(&mut self, args: &[&str]) -> CommandResult<String> {
+        no_args("reset", args)?;
+        self.vm = VirtualMachine::with_limits(self.program, *self.vm.limits());
+        self.vm.keep_history(Self::HISTORY);
+        self.at_start = true;
+        Ok(format!("{}\n", self.listing_line(0)))
+    }
+
+    fn advance(&mut self, check_breakpoints: bool) -> Stop {
+        self.at_start = false;
+        if self.vm.terminated() {
+            return Stop::Terminated;
+        }
+        if let Err(e) = self.vm.execute_one() {
+            return Stop::Failed(e);
+        }
+        if self.vm.terminated() {
+            return Stop::Terminated;
+        }
+        if check_breakpoints {
+            if let Some(id) = self.hit_breakpoint() {
+                return Stop::Breakpoint(id);
+            }
+        }
+        Stop::Stepped
+    }
+
+    fn hit_breakpoint(&self) -> Option<usize> {
+        let state = self.vm.current_state();
+        self.breakpoints
+            .iter()
+            .position(|bp| match bp {
+                Some(Breakpoint::Instruction(i)) => *i == state.instruction,
+                Some(Breakpoint::Accumulator(c, v)) => c.holds(state.accumulator(), *v),
+                None => false,
+            })
+            .map(|i| i + 1)
+    }
+
+    fn report(&self, stop: Stop) -> String {
+        let current = self.listing_line(self.vm.current_state().instruction);
+        match stop {
+            Stop::Stepped => format!("{}\n", current),
+            Stop::Breakpoint(id) => format!(
+                "Breakpoint {} ({}) hit\n{}\n",
+                id,
+                self.breakpoints[id - 1].unwrap(),
+                current
+            ),
+            Stop::Terminated => format!(
+                "Program terminated with accumulator {}\n",
+                self.vm.current_state().accumulator()
+            ),
+            Stop::Failed(e) => format!("Execution failed: {}\n{}\n", e, current),
+        }
+    }
+
+    fn listing_line(&self, at: usize) -> String {
+        let marker = if at == self.vm.current_state().instruction {
+            "=>"
+        } else {
+            "  "
+        };
+        let bp = if self
+            .breakpoints
+            .contains(&Some(Breakpoint::Instruction(at)))
+        {
+            "*"
+        } else {
+            " "
+        };
+        match self.program.get_instr(at) {
+            Some(instr) => format!("{} {} {:>4}: {}", marker, bp, at, instr),
+            None => format!("{} {} {:>4}: <end>", marker, bp, at),
+        }
+    }
+}
+
+/// Drives an interactive session: shows `prompt`, reads a line, writes what `execute` made of
+/// it, and repeats until `input` is exhausted or `execute` returns `None`.
+pub(super) fn run_session<R: BufRead, W: Write>(
+    input: R,
+    mut out: W,
+    prompt: &str,
+    echo: bool,
+    mut execute: impl FnMut(&str) -> Option<CommandResult<String>>,
+) -> io::Result<()> {
+    write!(out, "{}", prompt)?;
+    out.flush()?;
+    for line in input.lines() {
+        let line = line?;
+        if echo {
+            writeln!(out, "{}", line)?;
+        }
+        match execute(&line) {
+            Some(Ok(s)) => write!(out, "{}", s)?,
+            Some(Err(e)) => writeln!(out, "error: {}", e)?,
+            None => return Ok(()),
+        }
+        write!(out, "{}", prompt)?;
+        out.flush()?;
+    }
+    writeln!(out)
+}
+
+pub(super) fn parse_arg<T: FromStr>(command: &str, arg: &str) -> Result<T, CommandError> {
+    arg.parse()
+        .map_err(|_| CommandError::InvalidArgument(command.to_string(), arg.to_string()))
+}
+
+pub(super) fn no_args(command: &str, args: &[&str]) -> Result<(), CommandError> {
+    if args.is_empty() {
+        Ok(())
+    } else {
+        Err(CommandError::TooManyArguments(command.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGRAM: [&str; 9] = [
+        "nop +0", "acc +1", "jmp +4", "acc +3", "jmp -3", "acc -99", "acc +1", "jmp -4", "acc +6",
+    ];
+
+    fn transcript(script: &[&str]) -> Vec<String> {
+        let p = Program::parse_lines(&PROGRAM).unwrap();
+        let mut debugger = Debugger::new(&p);
+        let mut out = Vec::new();
+        let script = script.join("\n");
+        debugger.run(script.as_bytes(), &mut out, true).unwrap();
+        String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn steps() {
+        let out = transcript(&["step", "step 2", "", "print"]);
+        assert_eq!(
+            vec![
+                "(dbg) step",
+                "=>      1: acc +1",
+                "(dbg) step 2",
+                "=>      6: acc +1",
+                "(dbg) ",
+                "=>      3: acc +3",
+                "(dbg) print",
+                "instruction: 3",
+                "accumulator: 2",
+                "(dbg) ",
+            ],
+            out
+        );
+    }
+
+    #[test]
+    fn steps_back() {
+        let out = transcript(&["step 3", "back 2", "back 5", "print"]);
+        assert_eq!(
+            vec![
+                "(dbg) step 3",
+                "=>      6: acc +1",
+                "(dbg) back 2",
+                "=>      1: acc +1",
+                "(dbg) back 5",
+                "Reached the start of the history",
+                "=>      0: nop +0",
+                "(dbg) print",
+                "instruction: 0",
+                "accumulator: 0",
+                "(dbg) ",
+            ],
+            out
+        );
+    }
+
+    #[test]
+    fn instruction_breakpoints() {
+        let out = transcript(&[
+            "break 7", "continue", "delete 1", "continue", "quit", "print",
+        ]);
+        assert_eq!(
+            vec![
+                "(dbg) break 7",
+                "Breakpoint 1 at instruction 7",
+                "(dbg) continue",
+                "Breakpoint 1 (instruction 7) hit",
+                "=> *    7: jmp -4",
+                "(dbg) delete 1",
+                "Deleted breakpoint 1 at instruction 7",
+                "(dbg) continue",
+                "Execution failed: Infinite loop detected at instruction 1",
+                "=>      4: jmp -3",
+                "(dbg) quit",
+            ],
+            out
+        );
+    }
+
+    #[test]
+    fn breakpoint_on_first_instruction() {
+        let out = transcript(&["b 0", "c", "c", "r", "c"]);
+        assert_eq!(
+            vec![
+                "(dbg) b 0",
+                "Breakpoint 1 at instruction 0",
+                "(dbg) c",
+                "Breakpoint 1 (instruction 0) hit",
+                "=> *    0: nop +0",
+                "(dbg) c",
+                "Execution failed: Infinite loop detected at instruction 1",
+                "=>      4: jmp -3",
+                "(dbg) r",
+                "=> *    0: nop +0",
+                "(dbg) c",
+                "Breakpoint 1 (instruction 0) hit",
+                "=> *    0: nop +0",
+                "(dbg) ",
+            ],
+            out
+        );
+    }
+
+    #[test]
+    fn accumulator_breakpoints() {
+        let out = transcript(&["b acc >= 2", "c", "i", "c", "r", "b acc == 5", "c"]);
+        assert_eq!(
+            vec![
+                "(dbg) b acc >= 2",
+                "Breakpoint 1 at acc >= 2",
+                "(dbg) c",
+                "Breakpoint 1 (acc >= 2) hit",
+                "=>      7: jmp -4",
+                "(dbg) i",
+                "  1: acc >= 2",
+                "(dbg) c",
+                "Breakpoint 1 (acc >= 2) hit",
+                "=>      3: acc +3",
+                "(dbg) r",
+                "=>      0: nop +0",
+                "(dbg) b acc == 5",
+                "Breakpoint 2 at acc == 5",
+                "(dbg) c",
+                "Breakpoint 1 (acc >= 2) hit",
+                "=>      7: jmp -4",
+                "(dbg) ",
+            ],
+            out
+        );
+    }
+
+    #[test]
+    fn listing() {
+        let out = transcript(&["b 2", "s", "list 1", "l 0", "l 18446744073709551615"]);
+        assert_eq!(
+            vec![
+                "(dbg) b 2",
+                "Breakpoint 1 at instruction 2",
+                "(dbg) s",
+                "=>      1: acc +1",
+                "(dbg) list 1",
+                "        0: nop +0",
+                "=>      1: acc +1",
+                "   *    2: jmp +4",
+                "(dbg) l 0",
+                "=>      1: acc +1",
+                "(dbg) l 18446744073709551615",
+                "        0: nop +0",
+                "=>      1: acc +1",
+                "   *    2: jmp +4",
+                "        3: acc +3",
+                "        4: jmp -3",
+                "        5: acc -99",
+                "        6: acc +1",
+                "        7: jmp -4",
+                "        8: acc +6",
+                "        9: <end>",
+                "(dbg) ",
+            ],
+            out
+        );
+    }
+
+    #[test]
+    fn termination() {
+        let p = Program::parse_lines(&["acc +2", "nop +0"]).unwrap();
+        let mut debugger = Debugger::new(&p);
+        assert_eq!(
+            Some(Ok("Program terminated with accumulator 2\n".to_string())),
+            debugger.execute("c")
+        );
+        assert_eq!(
+            Some(Ok("        1: nop +0\n=>      2: <end>\n".to_string())),
+            debugger.execute("list 1")
+        );
+        assert!(debugger.vm().terminated());
+    }
+
+    #[test]
+    fn errors() {
+        let out = transcript(&[
+            "frobnicate",
+            "step x",
+            "break acc ~ 2",
+            "break",
+            "delete 3",
+            "print 1",
+        ]);
+        assert_eq!(
+            vec![
+                "(dbg) frobnicate",
+                "error: Unknown command frobnicate",
+                "(dbg) step x",
+                "error: Invalid argument x for command step",
+                "(dbg) break acc ~ 2",
+                "error: Invalid argument ~ for command break",
+                "(dbg) break",
+                "error: Missing argument for command break",
+                "(dbg) delete 3",
+                "error: Invalid argument 3 for command delete",
+                "(dbg) print 1",
+                "error: Too many arguments for command print",
+                "(dbg) ",
+            ],
+            out
+        );
+    }
+}
@@ -0,0 +1,188 @@
+use std::{
+    fmt,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use super::{ParseError, Span};
+
+/// A parse error together with the source line it was found on, rendered compiler-style.
+#[derive(Debug, PartialEq)]
+pub struct Diagnostic {
+    pub span: Span,
+    pub error: ParseError,
+    pub source: String,
+}
+
+impl Diagnostic {
+    /// Creates a diagnostic for the byte `range` of `source`, which is line `line` (one-based).
+    pub fn new(line: usize, source: &str, range: Range<usize>, error: ParseError) -> Self {
+        let column = |at: usize| source[..at].chars().count() + 1;
+        Diagnostic {
+            span: Span {
+                line,
+                start: column(range.start),
+                end: column(range.end),
+            },
+            error,
+            source: source.to_string(),
+        }
+    }
+
+    fn render(&self, f: &mut fmt::Formatter<'_>, origin: Option<&Path>) -> fmt::Result {
+        let gutter = " ".repeat(self.span.line.to_string().len());
+        writeln!(f, "error: {}", self.error)?;
+        match origin {
+            Some(path) => writeln!(
+                f,
+                "{}--> {}:{}:{}",
+                gutter,
+                path.display(),
+                self.span.line,
+                self.span.start
+            )?,
+            None => writeln!(f, "{}--> {}:{}", gutter, self.span.line, self.span.start)?,
+        }
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", self.span.line, self.source)?;
+        // Keep tabs so that the caret lines up with the source however tabs are displayed.
+        let indent: String = self
+            .source
+            .chars()
+            .take(self.span.start - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let width = (self.span.end - self.span.start).max(1);
+        writeln!(f, "{} | {}{}", gutter, indent, "^".repeat(width))
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.render(f, None)
+    }
+}
+
+/// Every error found in a program, in source order.
+#[derive(Debug, PartialEq)]
+pub struct Diagnostics {
+    pub origin: Option<PathBuf>,
+    pub errors: Vec<Diagnostic>,
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, d) in self.errors.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            d.render(f, self.origin.as_deref())?;
+        }
+        match self.errors.len() {
+            1 => write!(f, "\nerror: could not parse the program due to 1 error"),
+            n => write!(
+                f,
+                "\nerror: could not parse the program due to {} errors",
+                n
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{CodeParseError, InstructionSet, Program};
+
+    #[test]
+    fn renders_errors() {
+        let d = Diagnostic::new(
+            12,
+            "\tacc xyz",
+            5..8,
+            ParseError::UnparseableParameter("acc".to_string(), "xyz".to_string()),
+        );
+        assert_eq!(
+            Span {
+                line: 12,
+                start: 6,
+                end: 9
+            },
+            d.span
+        );
+        assert_eq!(
+            "error: Unparseable parameter xyz for instruction acc\n\
+             \x20 --> 12:6\n\
+             \x20  |\n\
+             12 | \tacc xyz\n\
+             \x20  | \t    ^^^\n",
+            d.to_string()
+        );
+    }
+
+    #[test]
+    fn collects_all_errors() {
+        let lines = ["nop +0", "frob +1", "acc", "jmp +1", "  mul x"];
+        let p = Program::parse_lines_all_with(&lines, &InstructionSet::new());
+        let diagnostics = match p {
+            Err(CodeParseError::Diagnostics(d)) => d,
+            _ => panic!("expected diagnostics"),
+        };
+        assert_eq!(
+            vec![(2, 1, 5), (3, 4, 4), (5, 3, 6)],
+            diagnostics
+                .errors
+                .iter()
+                .map(|d| (d.span.line, d.span.start, d.span.end))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            "error: Unknown instruction frob\n\
+             \x20--> 2:1\n\
+             \x20 |\n\
+             2 | frob +1\n\
+             \x20 | ^^^^\n\
+             \n\
+             error: Missing parameter for instruction acc\n\
+             \x20--> 3:4\n\
+             \x20 |\n\
+             3 | acc\n\
+             \x20 |    ^\n\
+             \n\
+             error: Unknown instruction mul\n\
+             \x20--> 5:3\n\
+             \x20 |\n\
+             5 |   mul x\n\
+             \x20 |   ^^^\n\
+             \n\
+             error: could not parse the program due to 3 errors",
+            diagnostics.to_string()
+        );
+
+        let p = Program::parse_lines_all(&["nop +0", "jmp -1"]).unwrap();
+        assert_eq!(2, p.len());
+    }
+
+    #[test]
+    fn names_the_file() {
+        let d = Diagnostics {
+            origin: Some(PathBuf::from("prog.txt")),
+            errors: vec![Diagnostic::new(
+                1,
+                "nop",
+                3..3,
+                ParseError::MissingParameter("nop".to_string()),
+            )],
+        };
+        assert_eq!(
+            "error: Missing parameter for instruction nop\n\
+             \x20--> prog.txt:1:4\n\
+             \x20 |\n\
+             1 | nop\n\
+             \x20 |    ^\n\
+             \n\
+             error: could not parse the program due to 1 error",
+            d.to_string()
+        );
+    }
+}
@@ -1,7 +1,13 @@
-use std::path::{Path, PathBuf};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use thiserror::Error;
 
+use super::Diagnostics;
+
 #[derive(Error, Debug, PartialEq)]
 pub enum ParseError {
     #[error("Unparseable line {0}")]
@@ -12,12 +18,60 @@ pub enum ParseError {
     MissingParameter(String),
     #[error("Unparseable parameter {1} for instruction {0}")]
     UnparseableParameter(String, String),
+    #[error("Invalid symbol name {0}")]
+    InvalidSymbol(String),
+    #[error("Undefined symbol {0}")]
+    UndefinedSymbol(String),
+    #[error("Symbol {0} is already defined")]
+    DuplicateSymbol(String),
+}
+
+/// One-based line and column range (end exclusive) in the source text.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Span {
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}-{}", self.line, self.start, self.end)
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum BytecodeError {
+    #[error("missing magic number")]
+    InvalidMagic,
+    #[error("unsupported version {0}")]
+    UnsupportedVersion(u8),
+    #[error("checksum mismatch")]
+    ChecksumMismatch,
+    #[error("unexpected end of data")]
+    UnexpectedEnd,
+    #[error("invalid varint")]
+    InvalidVarint,
+    #[error("invalid UTF-8 in instruction name")]
+    InvalidUtf8,
+    #[error("unknown instruction {0}")]
+    UnknownInstruction(String),
+    #[error("invalid opcode {0}")]
+    InvalidOpcode(u64),
+    #[error("operand {0} is out of range")]
+    OperandOutOfRange(i64),
+    #[error("trailing bytes after the last instruction")]
+    TrailingBytes,
 }
 
 #[derive(Error, Debug)]
 pub enum CodeParseError {
-    #[error("{error} at line {line}")]
-    AtLine { line: usize, error: ParseError },
+    #[error("{error} at {span}")]
+    AtSpan { span: Span, error: ParseError },
+    #[error("{0}")]
+    Diagnostics(Diagnostics),
+    #[error("invalid bytecode at offset {offset}: {error}")]
+    InvalidBytecode { offset: usize, error: BytecodeError },
     #[error("cannot load file {path} because of {error}")]
     IOError {
         path: PathBuf,
@@ -26,8 +80,8 @@ pub enum CodeParseError {
 }
 
 impl CodeParseError {
-    pub fn at_line(line: usize, error: ParseError) -> CodeParseError {
-        CodeParseError::AtLine { line, error }
+    pub fn at_span(span: Span, error: ParseError) -> CodeParseError {
+        CodeParseError::AtSpan { span, error }
     }
 
     pub fn from_io<P: AsRef<Path>>(path: P, error: std::io::Error) -> CodeParseError {
@@ -38,10 +92,62 @@ impl CodeParseError {
     }
 }
 
-#[derive(Error, Debug, PartialEq)]
+#[derive(Error, Debug, Clone, PartialEq)]
 pub enum ExecutionError {
     #[error("The program tried to access instruction at {0} but it is not valid")]
-    InvalidAccess(usize),
+    InvalidAccess(isize),
     #[error("Infinite loop detected at instruction {0}")]
     InfiniteLoop(usize),
+    #[error("The program returned to instruction {0} with accumulator {1}")]
+    RepeatedState(usize, i64),
+    #[error("The program exceeded the limit of {0} steps")]
+    StepLimitExceeded(u64),
+    #[error("The program exceeded its time budget of {0:?}")]
+    TimeLimitExceeded(Duration),
+    #[error("The program is waiting for a value at instruction {0}")]
+    Blocked(usize),
+    #[error("Instruction {0} rewrites the program, which this machine does not own")]
+    ReadOnlyProgram(usize),
+    #[error("Arithmetic overflow at instruction {0}")]
+    Overflow(usize),
+    #[error("The program used register {0} which is not defined")]
+    UnknownRegister(String),
+    #[error("The program tried to access memory at {0} but it is not valid")]
+    InvalidAddress(i64),
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum SchedulerError {
+    #[error("Deadlock: machines {0:?} are all waiting for values")]
+    Deadlock(Vec<usize>),
+    #[error("Machine {0} failed: {1}")]
+    Failed(usize, ExecutionError),
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum CommandError {
+    #[error("Unknown command {0}")]
+    UnknownCommand(String),
+    #[error("Missing argument for command {0}")]
+    MissingArgument(String),
+    #[error("Too many arguments for command {0}")]
+    TooManyArguments(String),
+    #[error("Invalid argument {1} for command {0}")]
+    InvalidArgument(String, String),
+    #[error("Cannot access {0}: {1}")]
+    Io(String, String),
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum RepairError {
+    #[error("Instruction {0} is not supported by the repair search")]
+    UnsupportedInstruction(usize),
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum AnalysisError {
+    #[error("Instruction {0} jumps to {1} which is out of bounds")]
+    InvalidJump(usize, isize),
+    #[error("The program never terminates")]
+    NeverTerminates,
 }
@@ -0,0 +1,397 @@
+use std::{
+    fs,
+    io::{self, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use super::{ExecutionError, ExecutionResult, Instruction, Program, VirtualMachine};
+
+/// Where shrunk failures are saved, one program per file, and replayed from by the tests.
+pub const REGRESSIONS_DIR: &str = "./inputs/fuzz";
+
+/// SplitMix64, which is plenty for generating test programs reproducibly from a seed.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `range`, which must not be empty.
+    pub fn range(&mut self, range: Range<i64>) -> i64 {
+        let width = (range.end - range.start) as u64;
+        range.start + (self.next_u64() % width) as i64
+    }
+
+    pub fn chance(&mut self, p: f64) -> bool {
+        ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < p
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratorConfig {
+    len: Range<usize>,
+    jump_density: f64,
+    nop_density: f64,
+    max_jump: isize,
+    max_acc: i64,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        GeneratorConfig::new()
+    }
+}
+
+impl GeneratorConfig {
+    pub fn new() -> Self {
+        GeneratorConfig {
+            len: 0..20,
+            jump_density: 0.3,
+            nop_density: 0.2,
+            max_jump: 6,
+            max_acc: 50,
+        }
+    }
+
+    pub fn len(mut self, len: Range<usize>) -> Self {
+        self.len = len;
+        self
+    }
+
+    /// Probability of each instruction being a `jmp`.
+    pub fn jump_density(mut self, jump_density: f64) -> Self {
+        self.jump_density = jump_density;
+        self
+    }
+
+    /// Probability of each instruction being a `nop`. The rest are `acc`.
+    pub fn nop_density(mut self, nop_density: f64) -> Self {
+        self.nop_density = nop_density;
+        self
+    }
+
+    /// Largest jump distance; jumps may leave the program.
+    pub fn max_jump(mut self, max_jump: isize) -> Self {
+        self.max_jump = max_jump;
+        self
+    }
+
+    pub fn max_acc(mut self, max_acc: i64) -> Self {
+        self.max_acc = max_acc;
+        self
+    }
+}
+
+pub struct ProgramGenerator {
+    config: GeneratorConfig,
+    rng: Rng,
+}
+
+impl ProgramGenerator {
+    pub fn new(config: GeneratorConfig, seed: u64) -> Self {
+        ProgramGenerator {
+            config,
+            rng: Rng::new(seed),
+        }
+    }
+
+    pub fn instructions(&mut self) -> Vec<Instruction> {
+        let (c, rng) = (&self.config, &mut self.rng);
+        let len = rng.range(c.len.start as i64..c.len.end.max(c.len.start + 1) as i64);
+        (0..len)
+            .map(|_| {
+                let jump = rng.range(-c.max_jump as i64..c.max_jump as i64 + 1) as isize;
+                if rng.chance(c.jump_density) {
+                    Instruction::Jmp(jump)
+                } else if rng.chance(c.nop_density / (1.0 - c.jump_density)) {
+                    Instruction::Nop(jump)
+                } else {
+                    Instruction::Acc(rng.range(-c.max_acc..c.max_acc + 1))
+                }
+            })
+            .collect()
+    }
+
+    pub fn program(&mut self) -> Program {
+        Program::from(self.instructions())
+    }
+
+    /// Source text of a generated program with random damage, for fuzzing the parser.
+    pub fn mutated_text(&mut self) -> Vec<String> {
+        const NOISE: &[&str] = &[
+            "",
+            " ",
+            "\t",
+            "+",
+            "-",
+            "--1",
+            "99999999999999999999",
+            "x",
+            "é",
+            "nop",
+            "jmp",
+            "#",
+        ];
+        let mut lines: Vec<String> = self.instructions().iter().map(|i| i.to_string()).collect();
+        let mutations = self.rng.range(0..4);
+        for _ in 0..mutations {
+            if lines.is_empty() {
+                lines.push(String::new());
+            }
+            let at = self.rng.range(0..lines.len() as i64) as usize;
+            let line = &mut lines[at];
+            let noise = NOISE[self.rng.range(0..NOISE.len() as i64) as usize];
+            let boundaries: Vec<usize> = line
+                .char_indices()
+                .map(|(i, _)| i)
+                .chain(std::iter::once(line.len()))
+                .collect();
+            let pos = boundaries[self.rng.range(0..boundaries.len() as i64) as usize];
+            match self.rng.range(0..4) {
+                0 => line.insert_str(pos, noise),
+                1 => line.truncate(pos),
+                2 => *line = line.replacen(' ', noise, 1),
+                _ => *line = noise.to_string(),
+            }
+        }
+        lines
+    }
+}
+
+/// A deliberately simple interpreter for plain `nop`/`acc`/`jmp` programs, written separately
+/// from `VirtualMachine` so the two can be checked against each other.
+pub fn reference_execute(instructions: &[Instruction]) -> (ExecutionResult<i64>, i64) {
+    let len = instructions.len() as isize;
+    let mut visited = vec![false; instructions.len() + 1];
+    let (mut at, mut acc) = (0isize, 0i64);
+    visited[0] = true;
+    while at != len {
+        let (next, delta) = match instructions[at as usize] {
+            Instruction::Nop(_) => (at + 1, 0),
+            Instruction::Acc(i) => (at + 1, i),
+            Instruction::Jmp(i) => (at.saturating_add(i), 0),
+            Instruction::Custom(..) => panic!("the reference model has no custom instructions"),
+        };
+        if next < 0 || next > len {
+            return (Err(ExecutionError::InvalidAccess(next)), acc);
+        }
+        if visited[next as usize] {
+            return (Err(ExecutionError::InfiniteLoop(next as usize)), acc);
+        }
+        visited[next as usize] = true;
+        acc = match acc.checked_add(delta) {
+            Some(acc) => acc,
+            None => return (Err(ExecutionError::Overflow(at as usize)), acc),
+        };
+        at = next;
+    }
+    (Ok(acc), acc)
+}
+
+/// Whether the VM disagrees with the reference model on a program.
+pub fn disagrees(instructions: &[Instruction]) -> bool {
+    let program = Program::from(instructions.to_vec());
+    let mut vm = VirtualMachine::new(&program);
+    let actual = (vm.execute(), vm.current_state().accumulator());
+    actual != reference_execute(instructions)
+}
+
+/// Whether parsing a text misbehaves: the two parse modes must agree, and everything that
+/// parses must print back to something that parses to the same instruction.
+pub fn parse_misbehaves(lines: &[String]) -> bool {
+    let lines: Vec<&str> = lines.iter().map(String::as_ref).collect();
+    let first = Program::parse_lines(&lines);
+    let all = Program::parse_lines_all(&lines);
+    if first.is_ok() != all.is_ok() {
+        return true;
+    }
+    lines.iter().any(|l| match Instruction::parse(l) {
+        Ok(i) => Instruction::parse(&i.to_string()) != Ok(i),
+        Err(_) => false,
+    })
+}
+
+/// Repeatedly removes instructions and moves operands towards zero while `fails` holds.
+pub fn shrink<F: Fn(&[Instruction]) -> bool>(
+    mut instructions: Vec<Instruction>,
+    fails: F,
+) -> Vec<Instruction> {
+    let mut progress = true;
+    while progress {
+        progress = false;
+        let mut at = 0;
+        while at < instructions.len() {
+            let mut candidate = instructions.clone();
+            candidate.remove(at);
+            if fails(&candidate) {
+                instructions = candidate;
+                progress = true;
+            } else {
+                at += 1;
+            }
+        }
+        for at in 0..instructions.len() {
+            for simpler in simplifications(instructions[at]) {
+                let mut candidate = instructions.clone();
+                candidate[at] = simpler;
+                if fails(&candidate) {
+                    instructions = candidate;
+                    progress = true;
+                    break;
+                }
+            }
+        }
+    }
+    instructions
+}
+
+fn simplifications(instr: Instruction) -> Vec<Instruction> {
+    let halve = |i: i64| if i / 2 == i { vec![] } else { vec![0, i / 2] };
+    match instr {
+        Instruction::Nop(i) if i != 0 => vec![Instruction::Nop(0)],
+        Instruction::Acc(i) => halve(i).into_iter().map(Instruction::Acc).collect(),
+        Instruction::Jmp(i) => halve(i as i64)
+            .into_iter()
+            .map(|i| Instruction::Jmp(i as isize))
+            .chain(std::iter::once(Instruction::Nop(0)))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Failure {
+    pub seed: u64,
+    pub instructions: Vec<Instruction>,
+}
+
+impl Failure {
+    /// Saves the reproducer as a program file in `dir`, returning its path.
+    pub fn save<P: AsRef<Path>>(&self, dir: P) -> io::Result<PathBuf> {
+        fs::create_dir_all(&dir)?;
+        let path = dir.as_ref().join(format!("seed-{:016x}.txt", self.seed));
+        let mut file = fs::File::create(&path)?;
+        write!(file, "{}", Program::from(self.instructions.clone()))?;
+        Ok(path)
+    }
+}
+
+/// Runs `cases` random programs, derived from `seed`, through the VM and the reference model,
+/// returning the first disagreement shrunk to a minimal program.
+pub fn fuzz_execution(config: &GeneratorConfig, seed: u64, cases: u64) -> Option<Failure> {
+    (seed..seed + cases).find_map(|seed| {
+        let instructions = ProgramGenerator::new(config.clone(), seed).instructions();
+        if disagrees(&instructions) {
+            Some(Failure {
+                seed,
+                instructions: shrink(instructions, disagrees),
+            })
+        } else {
+            None
+        }
+    })
+}
+
+/// Runs `cases` damaged program texts through the parser, returning the seeds of those that
+/// misbehave.
+pub fn fuzz_parser(config: &GeneratorConfig, seed: u64, cases: u64) -> Vec<u64> {
+    (seed..seed + cases)
+        .filter(|seed| {
+            let text = ProgramGenerator::new(config.clone(), *seed).mutated_text();
+            parse_misbehaves(&text)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generates_by_config() {
+        let config = GeneratorConfig::new().len(50..51).jump_density(1.0);
+        let mut g = ProgramGenerator::new(config, 1);
+        let p = g.instructions();
+        assert_eq!(50, p.len());
+        assert!(p.iter().all(|i| matches!(i, Instruction::Jmp(-6..=6))));
+
+        let config = GeneratorConfig::new().jump_density(0.0).nop_density(0.0);
+        let mut g = ProgramGenerator::new(config, 1);
+        assert!(g
+            .instructions()
+            .iter()
+            .all(|i| matches!(i, Instruction::Acc(-50..=50))));
+
+        let mut a = ProgramGenerator::new(GeneratorConfig::new(), 7);
+        let mut b = ProgramGenerator::new(GeneratorConfig::new(), 7);
+        assert_eq!(a.instructions(), b.instructions());
+    }
+
+    #[test]
+    fn vm_matches_reference() {
+        for config in &[
+            GeneratorConfig::new(),
+            GeneratorConfig::new().len(0..200).jump_density(0.05),
+            GeneratorConfig::new().len(1..5).max_jump(3),
+        ] {
+            assert_eq!(None, fuzz_execution(config, 0, 2000));
+        }
+    }
+
+    #[test]
+    fn parser_survives_mutations() {
+        assert_eq!(
+            Vec::<u64>::new(),
+            fuzz_parser(&GeneratorConfig::new(), 0, 2000)
+        );
+    }
+
+    #[test]
+    fn shrinks_failures() {
+        // Pretend that any program reaching an accumulator of at least 10 is a failure.
+        let fails = |p: &[Instruction]| reference_execute(p).1 >= 10;
+        let p = Program::parse_lines(&[
+            "nop +3", "acc +7", "jmp +2", "acc -50", "acc +40", "nop -1", "acc +3",
+        ])
+        .unwrap();
+        assert_eq!(
+            vec![Instruction::Acc(10)],
+            shrink(p.instructions().to_vec(), fails)
+        );
+    }
+
+    #[test]
+    fn replays_regressions() {
+        let mut replayed = 0;
+        for entry in fs::read_dir(REGRESSIONS_DIR).unwrap() {
+            let path = entry.unwrap().path();
+            let p = Program::parse_file(&path).unwrap();
+            assert!(!disagrees(p.instructions()), "{}", path.display());
+            replayed += 1;
+        }
+        assert!(replayed > 0);
+    }
+
+    #[test]
+    fn saves_failures() {
+        let dir = std::env::temp_dir().join("aoc_2020_fuzz_test");
+        let failure = Failure {
+            seed: 0xabc,
+            instructions: vec![Instruction::Acc(1), Instruction::Jmp(-1)],
+        };
+        let path = failure.save(&dir).unwrap();
+        assert_eq!("acc +1\njmp -1\n", fs::read_to_string(&path).unwrap());
+        fs::remove_file(path).unwrap();
+    }
+}
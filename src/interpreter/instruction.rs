@@ -1,31 +1,93 @@
-use std::str::FromStr;
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+    ops::Range,
+    str::FromStr,
+};
 
-use super::{ParseError, ParseResult};
+use super::{Flow, InstructionSet, Operation, ParseError, ParseResult};
+
+pub const BUILTIN_MNEMONICS: [&str; 3] = ["nop", "acc", "jmp"];
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Instruction {
     Nop(isize),
     Acc(i64),
     Jmp(isize),
+    Custom(&'static dyn Operation, i64),
+}
+
+// Equal custom operations have the same mnemonic, so that is what gets hashed.
+impl Eq for Instruction {}
+
+impl Hash for Instruction {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.mnemonic().hash(state);
+        match self {
+            Instruction::Nop(i) | Instruction::Jmp(i) => i.hash(state),
+            Instruction::Acc(i) | Instruction::Custom(_, i) => i.hash(state),
+        }
+    }
 }
 
 impl Instruction {
     pub fn parse(s: &str) -> ParseResult<Instruction> {
+        Self::parse_with(s, &InstructionSet::new())
+    }
+
+    pub fn parse_with(s: &str, set: &InstructionSet) -> ParseResult<Instruction> {
+        Self::parse_located(s, set).map_err(|(_, e)| e)
+    }
+
+    /// Parses a line, pointing errors at the byte range of `s` they refer to.
+    pub(super) fn parse_located(
+        s: &str,
+        set: &InstructionSet,
+    ) -> Result<Instruction, (Range<usize>, ParseError)> {
+        let start = s.len() - s.trim_start().len();
         let s = s.trim();
+        let end = start + s.len();
         if s.len() == 0 {
-            Err(ParseError::UnparseableLine(s.to_string()))
-        } else {
-            let instr_end = s.find(' ').unwrap_or(s.len());
-            match &s[..instr_end] {
-                x @ "nop" => Ok(Self::Nop(Self::parse_param(x, &s[instr_end..])?)),
-                x @ "acc" => Ok(Self::Acc(Self::parse_param(x, &s[instr_end..])?)),
-                x @ "jmp" => Ok(Self::Jmp(Self::parse_param(x, &s[instr_end..])?)),
-                x => Err(ParseError::UnknownInstruction(x.to_string())),
-            }
+            return Err((start..end, ParseError::UnparseableLine(s.to_string())));
         }
+
+        let instr_end = s.find(' ').unwrap_or(s.len());
+        let param = &s[instr_end..];
+        let param_start = start + instr_end + (param.len() - param.trim_start().len());
+        let param_range = param_start..end;
+        let result = match &s[..instr_end] {
+            x @ "nop" => Self::parse_param(x, param).map(Self::Nop),
+            x @ "acc" => Self::parse_param(x, param).map(Self::Acc),
+            x @ "jmp" => Self::parse_param(x, param).map(Self::Jmp),
+            x => match set.get(x) {
+                Some(op) => op.parse_operand(param).map(|arg| Self::Custom(op, arg)),
+                None => {
+                    let error = ParseError::UnknownInstruction(x.to_string());
+                    return Err((start..start + instr_end, error));
+                }
+            },
+        };
+        result.map_err(|e| (param_range, e))
     }
 
-    fn parse_param<F: FromStr>(instr: &str, s: &str) -> ParseResult<F> {
+    pub fn mnemonic(&self) -> &str {
+        match self {
+            Self::Nop(_) => "nop",
+            Self::Acc(_) => "acc",
+            Self::Jmp(_) => "jmp",
+            Self::Custom(op, _) => op.mnemonic(),
+        }
+    }
+
+    pub fn successors(&self) -> Option<Vec<Flow>> {
+        match self {
+            Self::Nop(_) | Self::Acc(_) => Some(vec![Flow::Next]),
+            Self::Jmp(i) => Some(vec![Flow::Jump(*i)]),
+            Self::Custom(op, arg) => op.successors(*arg),
+        }
+    }
+
+    pub(super) fn parse_param<F: FromStr>(instr: &str, s: &str) -> ParseResult<F> {
         let s = s.trim();
         if s.len() == 0 {
             Err(ParseError::MissingParameter(instr.to_string()))
@@ -41,9 +103,24 @@ impl Instruction {
     }
 }
 
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Nop(i) => write!(f, "nop {:+}", i),
+            Self::Acc(i) => write!(f, "acc {:+}", i),
+            Self::Jmp(i) => write!(f, "jmp {:+}", i),
+            Self::Custom(op, arg) => match op.format_operand(*arg) {
+                Some(arg) => write!(f, "{} {}", op.mnemonic(), arg),
+                None => f.write_str(op.mnemonic()),
+            },
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::interpreter::{Halt, Mul};
 
     #[test]
     fn parse_success() {
@@ -97,4 +174,24 @@ mod tests {
             Instruction::parse("nop a")
         );
     }
+
+    #[test]
+    fn parse_located() {
+        let set = InstructionSet::extended();
+        let error = |s| Instruction::parse_located(s, &set).unwrap_err().0;
+        assert_eq!(2..2, error("  "));
+        assert_eq!(2..5, error("  pon +1"));
+        assert_eq!(5..5, error("  nop"));
+        assert_eq!(7..10, error("  acc  1-2 "));
+        assert_eq!(6..7, error("  hlt 1"));
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!("nop +0", Instruction::Nop(0).to_string());
+        assert_eq!("acc -10", Instruction::Acc(-10).to_string());
+        assert_eq!("jmp +3", Instruction::Jmp(3).to_string());
+        assert_eq!("mul -2", Instruction::Custom(&Mul, -2).to_string());
+        assert_eq!("hlt", Instruction::Custom(&Halt, 0).to_string());
+    }
 }
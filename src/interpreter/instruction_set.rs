@@ -0,0 +1,367 @@
+use std::{any::Any, collections::HashMap, fmt, mem, ptr};
+
+use super::{
+    AddRegister, Channel, ExecutionResult, Instruction, Load, LoadIndirect, LoadMemory,
+    MulRegister, ParseError, ParseResult, Program, ProgramState, Rcv, Snd, Store, StoreIndirect,
+    StoreMemory, SubRegister, Toggle, BUILTIN_MNEMONICS,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Flow {
+    Next,
+    Jump(isize),
+    Halt,
+}
+
+pub trait Operation: Any + Sync {
+    fn mnemonic(&self) -> &str;
+
+    fn parse_operand(&self, s: &str) -> ParseResult<i64> {
+        Instruction::parse_param(self.mnemonic(), s)
+    }
+
+    fn format_operand(&self, operand: i64) -> Option<String> {
+        Some(format!("{:+}", operand))
+    }
+
+    /// All the ways control can leave the operation, or `None` if that depends on the state.
+    fn successors(&self, _operand: i64) -> Option<Vec<Flow>> {
+        None
+    }
+
+    /// Executes the operation on a copy of the current state. The instruction pointer is moved
+    /// by the VM according to the returned `Flow`, so changes to `state.instruction` are ignored.
+    /// The accumulator is changed with `set_accumulator` or the arithmetic methods of
+    /// `ProgramState`.
+    fn execute(&self, operand: i64, state: &mut ProgramState) -> ExecutionResult<Flow>;
+
+    /// Whether the operation rewrites the program it is part of. Only machines that own their
+    /// program run such operations, see `VirtualMachine::self_modifying`.
+    fn modifies_program(&self) -> bool {
+        false
+    }
+
+    /// The rewritten `program` for operations that `modifies_program`, or `None` if it stays as
+    /// it is. Called with the state before `execute`.
+    fn modify_program(
+        &self,
+        _operand: i64,
+        _state: &ProgramState,
+        _program: &Program,
+    ) -> ExecutionResult<Option<Program>> {
+        Ok(None)
+    }
+
+    /// Like `execute`, for operations that talk to other machines through `channel`.
+    fn execute_with(
+        &self,
+        operand: i64,
+        state: &mut ProgramState,
+        _channel: &mut dyn Channel,
+    ) -> ExecutionResult<Flow> {
+        self.execute(operand, state)
+    }
+}
+
+impl fmt::Debug for dyn Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.mnemonic())
+    }
+}
+
+/// Operations are the same if they are the same value of the same type. Operations without
+/// data cannot be told apart by address, so for them the type is enough.
+impl PartialEq for dyn Operation {
+    fn eq(&self, other: &Self) -> bool {
+        self.type_id() == other.type_id()
+            && (mem::size_of_val(self) == 0 || ptr::addr_eq(self, other))
+    }
+}
+
+#[derive(Default)]
+pub struct InstructionSet {
+    operations: HashMap<&'static str, &'static dyn Operation>,
+}
+
+impl InstructionSet {
+    pub fn new() -> Self {
+        InstructionSet {
+            operations: HashMap::new(),
+        }
+    }
+
+    /// Every operation this crate provides. It grows whenever one is added, so programs that
+    /// need a fixed instruction set should build it with `with` instead.
+    pub fn extended() -> Self {
+        Self::new()
+            .with(&Mul)
+            .with(&JumpIfZero)
+            .with(&JumpIfNotZero)
+            .with(&JumpByAccumulator)
+            .with(&Halt)
+            .with(&Snd)
+            .with(&Rcv)
+            .with(&Load)
+            .with(&Store)
+            .with(&AddRegister)
+            .with(&SubRegister)
+            .with(&MulRegister)
+            .with(&LoadMemory)
+            .with(&StoreMemory)
+            .with(&LoadIndirect)
+            .with(&StoreIndirect)
+            .with(&Toggle)
+    }
+
+    pub fn with(mut self, op: &'static dyn Operation) -> Self {
+        if !self.register(op) {
+            panic!("instruction {} is already defined", op.mnemonic());
+        }
+        self
+    }
+
+    pub fn register(&mut self, op: &'static dyn Operation) -> bool {
+        let mnemonic = op.mnemonic();
+        if BUILTIN_MNEMONICS.contains(&mnemonic) || self.operations.contains_key(mnemonic) {
+            false
+        } else {
+            self.operations.insert(mnemonic, op);
+            true
+        }
+    }
+
+    pub fn get(&self, mnemonic: &str) -> Option<&'static dyn Operation> {
+        self.operations.get(mnemonic).copied()
+    }
+}
+
+pub(super) fn parse_no_operand(instr: &str, s: &str) -> ParseResult<i64> {
+    let s = s.trim();
+    if s.is_empty() {
+        Ok(0)
+    } else {
+        Err(ParseError::UnparseableParameter(
+            instr.to_string(),
+            s.to_string(),
+        ))
+    }
+}
+
+pub struct Mul;
+
+impl Operation for Mul {
+    fn mnemonic(&self) -> &str {
+        "mul"
+    }
+
+    fn successors(&self, _: i64) -> Option<Vec<Flow>> {
+        Some(vec![Flow::Next])
+    }
+
+    fn execute(&self, operand: i64, state: &mut ProgramState) -> ExecutionResult<Flow> {
+        state.multiply_accumulator(operand)?;
+        Ok(Flow::Next)
+    }
+}
+
+pub struct JumpIfZero;
+
+impl Operation for JumpIfZero {
+    fn mnemonic(&self) -> &str {
+        "jz"
+    }
+
+    fn successors(&self, operand: i64) -> Option<Vec<Flow>> {
+        Some(vec![Flow::Next, Flow::Jump(operand as isize)])
+    }
+
+    fn execute(&self, operand: i64, state: &mut ProgramState) -> ExecutionResult<Flow> {
+        if state.accumulator() == 0 {
+            Ok(Flow::Jump(operand as isize))
+        } else {
+            Ok(Flow::Next)
+        }
+    }
+}
+
+pub struct JumpIfNotZero;
+
+impl Operation for JumpIfNotZero {
+    fn mnemonic(&self) -> &str {
+        "jnz"
+    }
+
+    fn successors(&self, operand: i64) -> Option<Vec<Flow>> {
+        Some(vec![Flow::Next, Flow::Jump(operand as isize)])
+    }
+
+    fn execute(&self, operand: i64, state: &mut ProgramState) -> ExecutionResult<Flow> {
+        if state.accumulator() != 0 {
+            Ok(Flow::Jump(operand as isize))
+        } else {
+            Ok(Flow::Next)
+        }
+    }
+}
+
+pub struct JumpByAccumulator;
+
+impl Operation for JumpByAccumulator {
+    fn mnemonic(&self) -> &str {
+        "jmpa"
+    }
+
+    fn parse_operand(&self, s: &str) -> ParseResult<i64> {
+        parse_no_operand(self.mnemonic(), s)
+    }
+
+    fn format_operand(&self, _: i64) -> Option<String> {
+        None
+    }
+
+    fn execute(&self, _: i64, state: &mut ProgramState) -> ExecutionResult<Flow> {
+        Ok(Flow::Jump(state.accumulator() as isize))
+    }
+}
+
+pub struct Halt;
+
+impl Operation for Halt {
+    fn mnemonic(&self) -> &str {
+        "hlt"
+    }
+
+    fn parse_operand(&self, s: &str) -> ParseResult<i64> {
+        parse_no_operand(self.mnemonic(), s)
+    }
+
+    fn format_operand(&self, _: i64) -> Option<String> {
+        None
+    }
+
+    fn successors(&self, _: i64) -> Option<Vec<Flow>> {
+        Some(vec![Flow::Halt])
+    }
+
+    fn execute(&self, _: i64, _: &mut ProgramState) -> ExecutionResult<Flow> {
+        Ok(Flow::Halt)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Neg;
+
+    impl Operation for Neg {
+        fn mnemonic(&self) -> &str {
+            "neg"
+        }
+
+        fn parse_operand(&self, s: &str) -> ParseResult<i64> {
+            parse_no_operand(self.mnemonic(), s)
+        }
+
+        fn execute(&self, _: i64, state: &mut ProgramState) -> ExecutionResult<Flow> {
+            state.set_accumulator(-state.accumulator());
+            Ok(Flow::Next)
+        }
+    }
+
+    #[test]
+    fn parse_custom() {
+        let set = InstructionSet::new().with(&Neg).with(&Mul);
+        assert_eq!(
+            Ok(Instruction::Custom(&Neg, 0)),
+            Instruction::parse_with("neg", &set)
+        );
+        assert_eq!(
+            Ok(Instruction::Custom(&Mul, -3)),
+            Instruction::parse_with("  mul   -3 ", &set)
+        );
+        assert_eq!(
+            Ok(Instruction::Jmp(2)),
+            Instruction::parse_with("jmp 2", &set)
+        );
+    }
+
+    #[test]
+    fn parse_custom_failure() {
+        let set = InstructionSet::new().with(&Neg).with(&Mul);
+        assert_eq!(
+            Err(ParseError::UnknownInstruction("neg".to_string())),
+            Instruction::parse("neg")
+        );
+        assert_eq!(
+            Err(ParseError::UnparseableParameter(
+                "neg".to_string(),
+                "1".to_string()
+            )),
+            Instruction::parse_with("neg 1", &set)
+        );
+        assert_eq!(
+            Err(ParseError::MissingParameter("mul".to_string())),
+            Instruction::parse_with("mul", &set)
+        );
+    }
+
+    #[test]
+    fn register_conflicts() {
+        let mut set = InstructionSet::new();
+        assert!(set.register(&Neg));
+        assert!(!set.register(&Neg));
+
+        struct FakeNop;
+        impl Operation for FakeNop {
+            fn mnemonic(&self) -> &str {
+                "nop"
+            }
+
+            fn execute(&self, _: i64, _: &mut ProgramState) -> ExecutionResult<Flow> {
+                Ok(Flow::Halt)
+            }
+        }
+        assert!(!set.register(&FakeNop));
+    }
+
+    #[test]
+    fn operations_compare_by_identity() {
+        struct Skip(isize);
+        impl Operation for Skip {
+            fn mnemonic(&self) -> &str {
+                "skip"
+            }
+
+            fn execute(&self, _: i64, _: &mut ProgramState) -> ExecutionResult<Flow> {
+                Ok(Flow::Jump(self.0))
+            }
+        }
+        static SKIP_ONE: Skip = Skip(2);
+        static SKIP_TWO: Skip = Skip(3);
+        struct FakeMul;
+        impl Operation for FakeMul {
+            fn mnemonic(&self) -> &str {
+                "mul"
+            }
+
+            fn execute(&self, _: i64, _: &mut ProgramState) -> ExecutionResult<Flow> {
+                Ok(Flow::Next)
+            }
+        }
+
+        assert_eq!(Instruction::Custom(&Mul, 1), Instruction::Custom(&Mul, 1));
+        assert_ne!(
+            Instruction::Custom(&Mul, 1),
+            Instruction::Custom(&FakeMul, 1)
+        );
+        assert_eq!(
+            Instruction::Custom(&SKIP_ONE, 1),
+            Instruction::Custom(&SKIP_ONE, 1)
+        );
+        assert_ne!(
+            Instruction::Custom(&SKIP_ONE, 1),
+            Instruction::Custom(&SKIP_TWO, 1)
+        );
+    }
+}
@@ -0,0 +1,56 @@
+use std::time::Duration;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LoopDetection {
+    /// Never treat a revisit as a loop; rely on the other limits instead.
+    Disabled,
+    /// Fail as soon as any instruction address is executed twice.
+    Address,
+    /// Fail only when the whole machine state repeats, which is a proof of non-termination.
+    State,
+}
+
+/// The limits a `VirtualMachine` enforces while running. The default detects loops by address
+/// revisit and has neither a step nor a time limit.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ExecutionLimits {
+    pub(super) loop_detection: LoopDetection,
+    pub(super) max_steps: Option<u64>,
+    pub(super) time_budget: Option<Duration>,
+}
+
+impl Default for ExecutionLimits {
+    fn default() -> Self {
+        ExecutionLimits::new()
+    }
+}
+
+impl ExecutionLimits {
+    pub fn new() -> Self {
+        ExecutionLimits {
+            loop_detection: LoopDetection::Address,
+            max_steps: None,
+            time_budget: None,
+        }
+    }
+
+    /// No loop detection and no limits at all.
+    pub fn unlimited() -> Self {
+        ExecutionLimits::new().loop_detection(LoopDetection::Disabled)
+    }
+
+    pub fn loop_detection(mut self, loop_detection: LoopDetection) -> Self {
+        self.loop_detection = loop_detection;
+        self
+    }
+
+    pub fn max_steps(mut self, max_steps: u64) -> Self {
+        self.max_steps = Some(max_steps);
+        self
+    }
+
+    pub fn time_budget(mut self, time_budget: Duration) -> Self {
+        self.time_budget = Some(time_budget);
+        self
+    }
+}
@@ -1,13 +1,57 @@
+mod analysis;
+mod arithmetic;
+mod assembler;
+mod builder;
+mod bytecode;
+mod channel;
+mod compiled;
+mod debugger;
+mod diagnostics;
 mod error;
+mod fuzz;
 mod instruction;
+mod instruction_set;
+mod limits;
+mod observer;
+mod optimizer;
+mod profiler;
 mod program;
+mod repair;
+mod repl;
+mod rewrite;
+mod scheduler;
+mod storage;
+mod trace;
 mod vm;
 
+pub use analysis::*;
+pub use arithmetic::*;
+pub use builder::*;
+pub use channel::*;
+pub use compiled::*;
+pub use debugger::*;
+pub use diagnostics::*;
 pub use error::*;
+pub use fuzz::*;
 pub use instruction::*;
+pub use instruction_set::*;
+pub use limits::*;
+pub use observer::*;
+pub use optimizer::*;
+pub use profiler::*;
 pub use program::*;
+pub use repair::*;
+pub use repl::*;
+pub use rewrite::*;
+pub use scheduler::*;
+pub use storage::*;
+pub use trace::*;
 pub use vm::*;
 
 pub type ParseResult<T> = std::result::Result<T, ParseError>;
 pub type CodeParseResult<T> = std::result::Result<T, CodeParseError>;
 pub type ExecutionResult<T> = std::result::Result<T, ExecutionError>;
+pub type CommandResult<T> = std::result::Result<T, CommandError>;
+pub type RepairResult<T> = std::result::Result<T, RepairError>;
+pub type AnalysisResult<T> = std::result::Result<T, AnalysisError>;
+pub type SchedulerResult<T> = std::result::Result<T, SchedulerError>;
@@ -0,0 +1,22 @@
+use super::{ExecutionError, Instruction, ProgramState};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    pub instruction: Instruction,
+    pub before: ProgramState,
+    pub after: ProgramState,
+}
+
+impl Step {
+    pub fn jump(&self) -> isize {
+        self.after.instruction as isize - self.before.instruction as isize
+    }
+}
+
+pub trait Observer {
+    fn before_step(&mut self, _state: &ProgramState, _instruction: &Instruction) {}
+
+    fn after_step(&mut self, _step: &Step) {}
+
+    fn on_error(&mut self, _state: &ProgramState, _error: &ExecutionError) {}
+}
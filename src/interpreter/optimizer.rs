@@ -0,0 +1,427 @@
+use std::collections::HashSet;
+
+use super::{Flow, Instruction, Program};
+
+/// A single rewrite over a program. Each pass keeps the outcome of `VirtualMachine::execute`
+/// (the final accumulator, or the kind of error), although loops may be detected at a different
+/// instruction.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Pass {
+    /// Merges runs of `acc` that no jump lands in the middle of. Only operands with the same sign
+    /// are merged, so the accumulator overflows in the merged run exactly when it did before.
+    MergeAcc,
+    /// Removes `nop`s, `acc +0` and `jmp +1`.
+    RemoveNops,
+    /// Points jumps that land on another jump straight at its target.
+    CollapseJumps,
+    /// Removes instructions that cannot be reached from the start.
+    RemoveDeadCode,
+}
+
+/// Instructions with jump targets resolved to absolute addresses.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Node {
+    Acc(i64),
+    Nop(isize),
+    Jump(isize),
+    /// A custom instruction that only ever falls through or halts.
+    Other(Instruction, bool),
+}
+
+/// Lowers a program, unless it contains custom instructions that jump - their operands cannot
+/// be retargeted, so such programs are left alone.
+fn lower(program: &Program) -> Option<Vec<Node>> {
+    program
+        .instructions()
+        .iter()
+        .enumerate()
+        .map(|(at, instr)| match instr {
+            Instruction::Acc(i) => Some(Node::Acc(*i)),
+            Instruction::Nop(i) => Some(Node::Nop(*i)),
+            Instruction::Jmp(i) => Some(Node::Jump((at as isize).saturating_add(*i))),
+            Instruction::Custom(..) => match instr.successors()?.as_slice() {
+                [Flow::Next] => Some(Node::Other(*instr, false)),
+                [Flow::Halt] => Some(Node::Other(*instr, true)),
+                _ => None,
+            },
+        })
+        .collect()
+}
+
+fn is_valid(target: isize, len: usize) -> bool {
+    target >= 0 && target as usize <= len
+}
+
+fn successors(nodes: &[Node], at: usize) -> Option<usize> {
+    match nodes[at] {
+        Node::Jump(t) if is_valid(t, nodes.len()) => Some(t as usize),
+        Node::Jump(_) | Node::Other(_, true) => None,
+        _ => Some(at + 1),
+    }
+}
+
+/// Builds the program without the removed nodes. A jump to a removed node goes to the next kept
+/// one, which is only correct because removed nodes either fall through or are never jumped to.
+/// Jumps out of bounds stay out of bounds, saturating at the ends of the range of offsets.
+fn raise(nodes: &[Node], removed: &[bool]) -> Program {
+    let len = nodes.len();
+    let mut resolved = vec![0; len + 1];
+    let mut next = removed.iter().filter(|r| !**r).count();
+    resolved[len] = next;
+    for at in (0..len).rev() {
+        if !removed[at] {
+            next -= 1;
+        }
+        resolved[at] = next;
+    }
+    let new_len = resolved[len];
+
+    let statements = (0..len)
+        .filter(|at| !removed[*at])
+        .map(|at| match nodes[at] {
+            Node::Acc(i) => Instruction::Acc(i),
+            Node::Nop(i) => Instruction::Nop(i),
+            Node::Other(instr, _) => instr,
+            Node::Jump(t) => {
+                let target = if t < 0 {
+                    t
+                } else if t as usize > len {
+                    (new_len + t as usize - len) as isize
+                } else {
+                    resolved[t as usize] as isize
+                };
+                Instruction::Jmp(target.saturating_sub(resolved[at] as isize))
+            }
+        })
+        .collect::<Vec<_>>();
+    Program::from(statements)
+}
+
+fn jump_targets(nodes: &[Node]) -> HashSet<usize> {
+    nodes
+        .iter()
+        .filter_map(|n| match n {
+            Node::Jump(t) if is_valid(*t, nodes.len()) => Some(*t as usize),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Whether adding `a` and then `b` never passes a value outside the range between the start and
+/// the total.
+fn same_sign(a: i64, b: i64) -> bool {
+    (a >= 0 && b >= 0) || (a <= 0 && b <= 0)
+}
+
+fn merge_acc(nodes: &mut [Node]) -> Vec<bool> {
+    let targets = jump_targets(nodes);
+    let mut removed = vec![false; nodes.len()];
+    let mut run_start = None;
+    for at in 0..nodes.len() {
+        match (nodes[at], run_start) {
+            (Node::Acc(i), Some(start)) if !targets.contains(&at) => match &mut nodes[start] {
+                Node::Acc(sum) if same_sign(*sum, i) && sum.checked_add(i).is_some() => {
+                    *sum += i;
+                    removed[at] = true;
+                }
+                _ => run_start = Some(at),
+            },
+            (Node::Acc(_), _) => run_start = Some(at),
+            _ => run_start = None,
+        }
+    }
+    removed
+}
+
+fn remove_nops(nodes: &[Node]) -> Vec<bool> {
+    nodes
+        .iter()
+        .enumerate()
+        .map(|(at, n)| match n {
+            Node::Nop(_) | Node::Acc(0) => true,
+            Node::Jump(t) => *t == at as isize + 1,
+            _ => false,
+        })
+        .collect()
+}
+
+fn collapse_jumps(nodes: &mut [Node]) -> Vec<bool> {
+    let len = nodes.len();
+    for at in 0..len {
+        let original = match nodes[at] {
+            Node::Jump(t) => t,
+            _ => continue,
+        };
+        let mut target = original;
+        let mut seen = vec![at];
+        while is_valid(target, len) && (target as usize) < len {
+            let next = match nodes[target as usize] {
+                Node::Jump(next) => next,
+                _ => break,
+            };
+            // A chain that ends in a cycle of jumps loops forever either way, keep it as it was.
+            if seen.contains(&(target as usize)) {
+                target = original;
+                break;
+            }
+            seen.push(target as usize);
+            target = next;
+        }
+        nodes[at] = Node::Jump(target);
+    }
+    vec![false; len]
+}
+
+fn remove_dead_code(nodes: &[Node]) -> Vec<bool> {
+    let mut removed = vec![true; nodes.len()];
+    let mut stack = vec![0];
+    while let Some(at) = stack.pop() {
+        if at < nodes.len() && removed[at] {
+            removed[at] = false;
+            if let Some(next) = successors(nodes, at) {
+                stack.push(next);
+            }
+        }
+    }
+    removed
+}
+
+impl Pass {
+    pub const ALL: [Pass; 4] = [
+        Pass::RemoveDeadCode,
+        Pass::CollapseJumps,
+        Pass::RemoveNops,
+        Pass::MergeAcc,
+    ];
+
+    pub fn run(&self, program: &Program) -> Program {
+        let mut nodes = match lower(program) {
+            Some(nodes) => nodes,
+            None => return Program::from(program.instructions().to_vec()),
+        };
+        let removed = match self {
+            Pass::MergeAcc => merge_acc(&mut nodes),
+            Pass::RemoveNops => remove_nops(&nodes),
+            Pass::CollapseJumps => collapse_jumps(&mut nodes),
+            Pass::RemoveDeadCode => remove_dead_code(&nodes),
+        };
+        raise(&nodes, &removed)
+    }
+}
+
+/// Runs a pipeline of passes until the program stops changing.
+#[derive(Debug, Clone)]
+pub struct Optimizer {
+    passes: Vec<Pass>,
+}
+
+impl Default for Optimizer {
+    fn default() -> Self {
+        Optimizer::new()
+    }
+}
+
+impl Optimizer {
+    pub fn new() -> Self {
+        Self::with_passes(&Pass::ALL)
+    }
+
+    pub fn with_passes(passes: &[Pass]) -> Self {
+        Optimizer {
+            passes: passes.to_vec(),
+        }
+    }
+
+    pub fn optimize(&self, program: &Program) -> Program {
+        let mut program = Program::from(program.instructions().to_vec());
+        loop {
+            let optimized = self
+                .passes
+                .iter()
+                .fold(Program::from(program.instructions().to_vec()), |p, pass| {
+                    pass.run(&p)
+                });
+            if optimized.instructions() == program.instructions() {
+                return optimized;
+            }
+            program = optimized;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{ExecutionError, ExecutionResult, InstructionSet, VirtualMachine};
+
+    fn optimize(lines: &[&str], passes: &[Pass]) -> Vec<String> {
+        let set = InstructionSet::extended();
+        let p = Program::parse_lines_with(lines, &set).unwrap();
+        let p = Optimizer::with_passes(passes).optimize(&p);
+        p.instructions().iter().map(|i| i.to_string()).collect()
+    }
+
+    #[test]
+    fn merges_acc() {
+        assert_eq!(
+            vec!["acc +3", "jmp -1", "acc +4"],
+            optimize(
+                &["acc +1", "acc +2", "jmp -2", "acc +1", "acc +3"],
+                &[Pass::MergeAcc]
+            )
+        );
+        // Sums that do not fit in an operand are left as they are.
+        assert_eq!(
+            vec!["acc +9223372036854775807", "acc +2"],
+            optimize(
+                &["acc +9223372036854775806", "acc +1", "acc +2"],
+                &[Pass::MergeAcc]
+            )
+        );
+        // The first two overflow on their own, whatever the accumulator was before.
+        assert_eq!(
+            vec!["acc +9223372036854775807", "acc +1", "acc -1"],
+            optimize(
+                &["acc +9223372036854775807", "acc +1", "acc -1"],
+                &Pass::ALL
+            )
+        );
+        assert_eq!(
+            vec!["acc +3", "acc -3", "acc +2"],
+            optimize(
+                &["acc +1", "acc +2", "acc -1", "acc -2", "acc +2"],
+                &[Pass::MergeAcc]
+            )
+        );
+    }
+
+    #[test]
+    fn keeps_huge_jumps_out_of_bounds() {
+        assert_eq!(
+            vec!["acc +1", "jmp +9223372036854775805"],
+            optimize(
+                &["nop +0", "acc +1", "jmp +9223372036854775807"],
+                &Pass::ALL
+            )
+        );
+        assert_eq!(
+            vec!["jmp -9223372036854775807"],
+            optimize(&["nop +0", "jmp -9223372036854775808"], &Pass::ALL)
+        );
+    }
+
+    #[test]
+    fn removes_nops() {
+        assert_eq!(
+            // The jump to the end becomes `jmp +1` and goes on the next round.
+            vec!["acc +1", "jmp -1", "jmp -2"],
+            optimize(
+                &["nop +5", "acc +1", "jmp +1", "jmp -2", "acc +0", "jmp -5", "jmp +2", "nop +0"],
+                &[Pass::RemoveNops]
+            )
+        );
+    }
+
+    #[test]
+    fn collapses_jumps() {
+        assert_eq!(
+            vec!["jmp +3", "jmp +2", "jmp +1", "acc +1", "jmp +0", "jmp +1", "jmp -1"],
+            optimize(
+                &["jmp +1", "jmp +2", "jmp -2", "acc +1", "jmp +0", "jmp +1", "jmp -1"],
+                &[Pass::CollapseJumps]
+            )
+        );
+    }
+
+    #[test]
+    fn removes_dead_code() {
+        assert_eq!(
+            vec!["jmp +1", "acc +1", "hlt"],
+            optimize(
+                &["jmp +3", "acc -1", "jmp -1", "acc +1", "hlt", "acc +2"],
+                &[Pass::RemoveDeadCode]
+            )
+        );
+    }
+
+    #[test]
+    fn leaves_dynamic_jumps_alone() {
+        let lines = ["nop +0", "acc +1", "acc +1", "jnz -1"];
+        assert_eq!(lines.to_vec(), optimize(&lines, &Pass::ALL));
+    }
+
+    #[test]
+    fn optimizes_example() {
+        let lines = [
+            "nop +0", "acc +1", "jmp +4", "acc +3", "jmp -3", "acc -99", "acc +1", "jmp -4",
+            "acc +6",
+        ];
+        assert_eq!(
+            vec!["acc +1", "jmp +3", "acc +3", "jmp -3", "acc +1", "jmp -3"],
+            optimize(&lines, &Pass::ALL)
+        );
+    }
+
+    /// Whether two runs end the same way. Loops may be caught at different instructions, and
+    /// out-of-bounds jumps go to different addresses.
+    fn same_outcome(a: &ExecutionResult<i64>, b: &ExecutionResult<i64>) -> bool {
+        match (a, b) {
+            (Ok(a), Ok(b)) => a == b,
+            (Err(ExecutionError::InfiniteLoop(_)), Err(ExecutionError::InfiniteLoop(_)))
+            | (Err(ExecutionError::InvalidAccess(_)), Err(ExecutionError::InvalidAccess(_)))
+            | (Err(ExecutionError::Overflow(_)), Err(ExecutionError::Overflow(_))) => true,
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn preserves_semantics() {
+        let set = InstructionSet::extended();
+        let mnemonics = ["nop", "acc", "jmp", "acc", "jmp", "mul", "hlt", "jz"];
+        let mut seed: u64 = 0x2020;
+        let mut next = |m: u64| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) % m
+        };
+        // Mostly small operands, with the odd one at the edge of the range to overflow the
+        // accumulator or jump far out of bounds.
+        let operand = |next: &mut dyn FnMut(u64) -> u64| match next(12) {
+            0 => i64::MAX - next(2) as i64,
+            1 => i64::MIN + next(2) as i64,
+            _ => next(9) as i64 - 4,
+        };
+        let mut pipelines: Vec<Vec<Pass>> = Pass::ALL.iter().map(|p| vec![*p]).collect();
+        pipelines.push(Pass::ALL.to_vec());
+        for round in 0..3000 {
+            let len = next(16) as usize;
+            // Every tenth program may contain a conditional jump, which disables the passes.
+            let kinds = if round % 10 == 0 { 8 } else { 7 };
+            let lines: Vec<String> = (0..len)
+                .map(|_| match mnemonics[next(kinds) as usize] {
+                    "hlt" => "hlt".to_string(),
+                    "mul" => format!("mul {:+}", next(3) as i64 - 1),
+                    m => format!("{} {:+}", m, operand(&mut next)),
+                })
+                .collect();
+            let lines: Vec<&str> = lines.iter().map(String::as_ref).collect();
+            let p = Program::parse_lines_with(&lines, &set).unwrap();
+            let expected = VirtualMachine::new(&p).execute();
+            for passes in &pipelines {
+                let q = Optimizer::with_passes(passes).optimize(&p);
+                let actual = VirtualMachine::new(&q).execute();
+                assert!(
+                    same_outcome(&expected, &actual) && q.len() <= p.len(),
+                    "{:?} with {:?}: {:?} became {:?} ({:?} vs {:?})",
+                    lines,
+                    passes,
+                    expected,
+                    q.instructions(),
+                    expected,
+                    actual
+                );
+            }
+        }
+    }
+}
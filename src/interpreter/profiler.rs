@@ -0,0 +1,233 @@
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use super::{Observer, Program, Step};
+
+/// What happened at one instruction over all observed runs.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Serialize)]
+pub struct SiteProfile {
+    pub executions: u64,
+    /// How often control did not fall through to the next instruction.
+    pub jumps_taken: u64,
+    /// Range of the accumulator on arrival at the instruction.
+    pub accumulator_min: Option<i64>,
+    pub accumulator_max: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct SiteRecord<'a> {
+    address: usize,
+    instruction: String,
+    #[serde(flatten)]
+    profile: &'a SiteProfile,
+}
+
+#[derive(Serialize)]
+struct ProfileRecord<'a> {
+    instructions: usize,
+    covered: usize,
+    steps: u64,
+    sites: Vec<SiteRecord<'a>>,
+}
+
+/// Counts executions, taken jumps and accumulator ranges per instruction. A profiler can observe
+/// several runs in a row to collect the coverage of a whole set of inputs.
+#[derive(Default)]
+pub struct Profiler {
+    sites: Vec<SiteProfile>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler { sites: Vec::new() }
+    }
+
+    pub fn site(&self, at: usize) -> SiteProfile {
+        self.sites.get(at).copied().unwrap_or_default()
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.sites.iter().map(|s| s.executions).sum()
+    }
+
+    /// Instructions of `program` that were never executed.
+    pub fn unreached(&self, program: &Program) -> Vec<usize> {
+        (0..program.len())
+            .filter(|at| self.site(*at).executions == 0)
+            .collect()
+    }
+
+    /// Instructions ordered from the most to the least executed, leaving out unreached ones.
+    pub fn hottest(&self) -> Vec<usize> {
+        let mut sites: Vec<_> = (0..self.sites.len())
+            .filter(|at| self.sites[*at].executions > 0)
+            .collect();
+        sites.sort_by_key(|at| std::cmp::Reverse(self.sites[*at].executions));
+        sites
+    }
+
+    pub fn write_listing<W: Write>(&self, program: &Program, mut out: W) -> io::Result<()> {
+        writeln!(
+            out,
+            "{:>10} {:>10} {:>25}  {:>5}: instruction",
+            "count", "taken", "accumulator", "addr"
+        )?;
+        for (at, instr) in program.instructions().iter().enumerate() {
+            let site = self.site(at);
+            match (site.accumulator_min, site.accumulator_max) {
+                (Some(min), Some(max)) => writeln!(
+                    out,
+                    "{:>10} {:>10} {:>25}  {:>5}: {}",
+                    site.executions,
+                    site.jumps_taken,
+                    format!("{}..={}", min, max),
+                    at,
+                    instr
+                )?,
+                _ => writeln!(
+                    out,
+                    "{:>10} {:>10} {:>25}  {:>5}: {}",
+                    "-", "-", "-", at, instr
+                )?,
+            }
+        }
+        let covered = program.len() - self.unreached(program).len();
+        writeln!(
+            out,
+            "{} of {} instructions executed, {} steps",
+            covered,
+            program.len(),
+            self.steps()
+        )
+    }
+
+    pub fn write_json<W: Write>(&self, program: &Program, mut out: W) -> io::Result<()> {
+        let sites: Vec<_> = (0..program.len()).map(|at| self.site(at)).collect();
+        let record = ProfileRecord {
+            instructions: program.len(),
+            covered: program.len() - self.unreached(program).len(),
+            steps: self.steps(),
+            sites: program
+                .instructions()
+                .iter()
+                .zip(&sites)
+                .enumerate()
+                .map(|(address, (instr, profile))| SiteRecord {
+                    address,
+                    instruction: instr.to_string(),
+                    profile,
+                })
+                .collect(),
+        };
+        serde_json::to_writer(&mut out, &record)?;
+        writeln!(out)
+    }
+}
+
+impl Observer for Profiler {
+    fn after_step(&mut self, step: &Step) {
+        let at = step.before.instruction;
+        if self.sites.len() <= at {
+            self.sites.resize(at + 1, SiteProfile::default());
+        }
+        let site = &mut self.sites[at];
+        let acc = step.before.accumulator();
+        site.executions += 1;
+        if step.jump() != 1 {
+            site.jumps_taken += 1;
+        }
+        site.accumulator_min = Some(site.accumulator_min.map_or(acc, |m| m.min(acc)));
+        site.accumulator_max = Some(site.accumulator_max.map_or(acc, |m| m.max(acc)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{ExecutionLimits, InstructionSet, VirtualMachine};
+
+    fn profile(program: &Program, runs: &[i64]) -> Profiler {
+        let mut profiler = Profiler::new();
+        for input in runs {
+            let mut vm = VirtualMachine::with_limits(program, ExecutionLimits::unlimited());
+            vm.send(*input);
+            vm.observe(&mut profiler);
+            vm.execute().unwrap();
+        }
+        profiler
+    }
+
+    fn countdown() -> Program {
+        let set = InstructionSet::extended();
+        Program::parse_lines_with(&["rcv", "jz +3", "acc -1", "jnz -1", "acc +7"], &set).unwrap()
+    }
+
+    #[test]
+    fn counts_executions() {
+        let p = countdown();
+        let profiler = profile(&p, &[3]);
+        assert_eq!(
+            SiteProfile {
+                executions: 3,
+                jumps_taken: 2,
+                accumulator_min: Some(0),
+                accumulator_max: Some(2)
+            },
+            profiler.site(3)
+        );
+        assert_eq!(0, profiler.site(1).jumps_taken);
+        assert_eq!(vec![2, 3, 0, 1, 4], profiler.hottest());
+        assert!(profiler.unreached(&p).is_empty());
+        assert_eq!(9, profiler.steps());
+
+        let profiler = profile(&p, &[0]);
+        assert_eq!(vec![2, 3], profiler.unreached(&p));
+    }
+
+    #[test]
+    fn writes_listing() {
+        let p = countdown();
+        let mut out = Vec::new();
+        profile(&p, &[0, 2]).write_listing(&p, &mut out).unwrap();
+        assert_eq!(
+            "     count      taken               accumulator   addr: instruction\n\
+             \x20        2          0                     0..=0      0: rcv\n\
+             \x20        2          1                     0..=2      1: jz +3\n\
+             \x20        2          0                     1..=2      2: acc -1\n\
+             \x20        2          1                     0..=1      3: jnz -1\n\
+             \x20        2          0                     0..=0      4: acc +7\n\
+             5 of 5 instructions executed, 10 steps\n",
+            String::from_utf8(out).unwrap()
+        );
+
+        let mut out = Vec::new();
+        profile(&p, &[0]).write_listing(&p, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(
+            "         -          -                         -      2: acc -1",
+            out.lines().nth(3).unwrap()
+        );
+    }
+
+    #[test]
+    fn writes_json() {
+        let p = Program::parse_lines(&["acc +2", "jmp +2", "acc +1"]).unwrap();
+        let mut profiler = Profiler::new();
+        let mut vm = VirtualMachine::new(&p);
+        vm.observe(&mut profiler);
+        vm.execute().unwrap();
+        let mut out = Vec::new();
+        profiler.write_json(&p, &mut out).unwrap();
+        assert_eq!(
+            concat!(
+                r#"{"instructions":3,"covered":2,"steps":2,"sites":["#,
+                r#"{"address":0,"instruction":"acc +2","executions":1,"jumps_taken":0,"accumulator_min":0,"accumulator_max":0},"#,
+                r#"{"address":1,"instruction":"jmp +2","executions":1,"jumps_taken":1,"accumulator_min":2,"accumulator_max":2},"#,
+                r#"{"address":2,"instruction":"acc +1","executions":0,"jumps_taken":0,"accumulator_min":null,"accumulator_max":null}"#,
+                "]}\n"
+            ),
+            String::from_utf8(out).unwrap()
+        );
+    }
+}
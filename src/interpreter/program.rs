@@ -1,34 +1,105 @@
-use std::{fs, io, io::BufRead, path::Path};
+use std::{fmt, fs, io, io::BufRead, ops::RangeBounds, path::Path};
 
-use super::{CodeParseError, CodeParseResult, Instruction};
+use super::{
+    CodeParseError, CodeParseResult, Diagnostic, Diagnostics, Instruction, InstructionSet,
+};
 
+#[derive(Debug, Clone)]
 pub struct Program {
     statements: Vec<Instruction>,
 }
 
 impl Program {
     pub fn parse_lines<'a>(lines: &'a [&'a str]) -> CodeParseResult<Self> {
+        Self::parse_lines_with(lines, &InstructionSet::new())
+    }
+
+    pub fn parse_lines_with<'a>(
+        lines: &'a [&'a str],
+        set: &InstructionSet,
+    ) -> CodeParseResult<Self> {
         let i = lines.iter().map(|x| *x);
-        Self::parse_iter(i)
+        Self::parse_iter(i, set)
     }
 
     pub fn parse_file<P: AsRef<Path> + Copy>(path: P) -> CodeParseResult<Self> {
-        let lines: io::Result<Vec<String>> =
-            fs::File::open(path).and_then(|file| io::BufReader::new(file).lines().collect());
-        let lines = lines.map_err(|e| CodeParseError::from_io(path, e))?;
-        let lines = lines.iter().map(String::as_ref);
-        Ok(Self::parse_iter(lines)?)
+        Self::parse_file_with(path, &InstructionSet::new())
     }
 
-    fn parse_iter<'a, I: Iterator<Item = &'a str>>(iter: I) -> CodeParseResult<Self> {
+    pub fn parse_file_with<P: AsRef<Path> + Copy>(
+        path: P,
+        set: &InstructionSet,
+    ) -> CodeParseResult<Self> {
+        let lines = read_lines(path)?;
+        Self::parse_iter(lines.iter().map(String::as_ref), set)
+    }
+
+    /// Stops at the first bad line, which is reported with the same one-based span as in
+    /// `parse_lines_all`.
+    fn parse_iter<'a, I: Iterator<Item = &'a str>>(
+        iter: I,
+        set: &InstructionSet,
+    ) -> CodeParseResult<Self> {
         let statements: CodeParseResult<Vec<_>> = iter
             .enumerate()
-            .map(|(i, l)| Instruction::parse(l).map_err(|e| CodeParseError::at_line(i, e)))
+            .map(|(i, line)| {
+                Instruction::parse_located(line, set).map_err(|(range, e)| {
+                    let d = Diagnostic::new(i + 1, line, range, e);
+                    CodeParseError::at_span(d.span, d.error)
+                })
+            })
             .collect();
         let statements = statements?;
         Ok(Program { statements })
     }
 
+    /// Like `parse_lines`, but reports every bad line instead of just the first one.
+    pub fn parse_lines_all<'a>(lines: &'a [&'a str]) -> CodeParseResult<Self> {
+        Self::parse_lines_all_with(lines, &InstructionSet::new())
+    }
+
+    pub fn parse_lines_all_with<'a>(
+        lines: &'a [&'a str],
+        set: &InstructionSet,
+    ) -> CodeParseResult<Self> {
+        Self::parse_all_iter(lines.iter().copied(), set, None)
+    }
+
+    pub fn parse_file_all<P: AsRef<Path> + Copy>(path: P) -> CodeParseResult<Self> {
+        Self::parse_file_all_with(path, &InstructionSet::new())
+    }
+
+    pub fn parse_file_all_with<P: AsRef<Path> + Copy>(
+        path: P,
+        set: &InstructionSet,
+    ) -> CodeParseResult<Self> {
+        let lines = read_lines(path)?;
+        Self::parse_all_iter(lines.iter().map(String::as_ref), set, Some(path.as_ref()))
+    }
+
+    fn parse_all_iter<'a, I: Iterator<Item = &'a str>>(
+        iter: I,
+        set: &InstructionSet,
+        origin: Option<&Path>,
+    ) -> CodeParseResult<Self> {
+        let mut statements = Vec::new();
+        let mut errors = Vec::new();
+        for (i, line) in iter.enumerate() {
+            match Instruction::parse_located(line, set) {
+                Ok(instr) => statements.push(instr),
+                Err((range, e)) => errors.push(Diagnostic::new(i + 1, line, range, e)),
+            }
+        }
+        if errors.is_empty() {
+            Ok(Program { statements })
+        } else {
+            Err(CodeParseError::Diagnostics(Diagnostics {
+                origin: origin.map(Path::to_path_buf),
+                errors,
+            }))
+        }
+    }
+
     pub fn get_instr(&self, at: usize) -> Option<&Instruction> {
         self.statements.get(at)
     }
@@ -37,14 +108,64 @@ impl Program {
         self.statements[at] = i;
     }
 
+    // Like the `Vec` methods they wrap, these leave jump offsets alone, so jumps across the
+    // edited range may need adjusting.
+
+    pub fn push(&mut self, i: Instruction) {
+        self.statements.push(i);
+    }
+
+    pub fn insert(&mut self, at: usize, i: Instruction) {
+        self.statements.insert(at, i);
+    }
+
+    pub fn remove(&mut self, at: usize) -> Instruction {
+        self.statements.remove(at)
+    }
+
+    /// Replaces the instructions in `range` with `replacement` and returns the removed ones.
+    pub fn splice<R, I>(&mut self, range: R, replacement: I) -> Vec<Instruction>
+    where
+        R: RangeBounds<usize>,
+        I: IntoIterator<Item = Instruction>,
+    {
+        self.statements.splice(range, replacement).collect()
+    }
+
     pub fn len(&self) -> usize {
         self.statements.len()
     }
+
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.statements
+    }
+}
+
+fn read_lines<P: AsRef<Path> + Copy>(path: P) -> CodeParseResult<Vec<String>> {
+    fs::File::open(path)
+        .and_then(|file| io::BufReader::new(file).lines().collect())
+        .map_err(|e| CodeParseError::from_io(path, e))
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for instr in &self.statements {
+            writeln!(f, "{}", instr)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<Vec<Instruction>> for Program {
+    fn from(statements: Vec<Instruction>) -> Self {
+        Program { statements }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::interpreter::{Halt, Mul, Span};
 
     #[test]
     fn parse_success() {
@@ -54,10 +175,57 @@ mod tests {
 
     #[test]
     fn parse_failure() {
-        let p = Program::parse_lines(&["nop 1", "err"]);
-        assert!(matches!(p, Err(CodeParseError::AtLine { line: 1, .. })));
+        let p = Program::parse_lines(&["nop 1", " err"]);
+        let span = Span {
+            line: 2,
+            start: 2,
+            end: 5,
+        };
+        assert!(matches!(p, Err(CodeParseError::AtSpan { span: s, .. }) if s == span));
 
         let p = Program::parse_file("this file does not exist.txt");
-        assert!(matches!(p, Err(CodeParseError::IOError { .. })))
+        assert!(matches!(p, Err(CodeParseError::IOError { .. })));
+        let p = Program::parse_file_all("this file does not exist.txt");
+        assert!(matches!(p, Err(CodeParseError::IOError { .. })));
+        assert_eq!(
+            Program::parse_file("./inputs/day08.txt")
+                .unwrap()
+                .instructions(),
+            Program::parse_file_all("./inputs/day08.txt")
+                .unwrap()
+                .instructions()
+        );
+    }
+
+    #[test]
+    fn parse_with_instruction_set() {
+        let set = InstructionSet::extended();
+        let p = Program::parse_lines_with(&["mul 2", "hlt"], &set).unwrap();
+        assert_eq!(
+            vec![Instruction::Custom(&Mul, 2), Instruction::Custom(&Halt, 0)],
+            p.statements
+        );
+
+        let p = Program::parse_lines(&["mul 2", "hlt"]);
+        assert!(matches!(
+            p,
+            Err(CodeParseError::AtSpan {
+                span: Span { line: 1, .. },
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn edits() {
+        let mut p = crate::program! { nop +0; acc +1; jmp -1 };
+        p.insert(1, Instruction::Acc(2));
+        p.push(Instruction::Acc(3));
+        assert_eq!(Instruction::Nop(0), p.remove(0));
+        let removed = p.splice(1..3, vec![Instruction::Nop(1)]);
+        assert_eq!(vec![Instruction::Acc(1), Instruction::Jmp(-1)], removed);
+        assert_eq!("acc +2\nnop +1\nacc +3\n", p.to_string());
+        p.splice(.., None);
+        assert_eq!(0, p.len());
     }
 }
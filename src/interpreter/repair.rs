@@ -0,0 +1,340 @@
+use super::{Instruction, Program, RepairError, RepairResult};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Repair {
+    pub index: usize,
+    pub replacement: Instruction,
+    pub accumulator: i64,
+}
+
+/// Finds single-instruction patches that make a program terminate without re-running it for
+/// every candidate. Only patches to instructions that actually get executed are reported - the
+/// rest cannot change the outcome. Like the `VirtualMachine` with `Arithmetic::Checked`, a
+/// patched program whose accumulator would overflow on the way does not count as repaired.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct RepairSearch {
+    replace_acc: bool,
+}
+
+struct Tree {
+    terminates: Vec<bool>,
+    /// What the rest of the run from each instruction adds to the accumulator in total, and the
+    /// lowest and highest amounts it has added at any point on the way.
+    suffix: Vec<i128>,
+    lowest: Vec<i128>,
+    highest: Vec<i128>,
+    enter: Vec<usize>,
+    exit: Vec<usize>,
+}
+
+impl Tree {
+    fn build(successors: &[Option<usize>], program: &Program) -> Tree {
+        let n = successors.len();
+        let mut sources = vec![Vec::new(); n + 1];
+        for (i, s) in successors.iter().enumerate() {
+            if let Some(s) = s {
+                sources[*s].push(i);
+            }
+        }
+
+        let mut tree = Tree {
+            terminates: vec![false; n + 1],
+            suffix: vec![0; n + 1],
+            lowest: vec![0; n + 1],
+            highest: vec![0; n + 1],
+            enter: vec![0; n + 1],
+            exit: vec![0; n + 1],
+        };
+        let mut clock = 0;
+        let mut stack = vec![(n, 0)];
+        tree.terminates[n] = true;
+        while let Some((node, child)) = stack.pop() {
+            if child == 0 {
+                tree.enter[node] = clock;
+                clock += 1;
+            }
+            match sources[node].get(child) {
+                Some(&src) => {
+                    stack.push((node, child + 1));
+                    tree.terminates[src] = true;
+                    let delta = acc_delta(program, src) as i128;
+                    tree.suffix[src] = delta + tree.suffix[node];
+                    tree.lowest[src] = (delta + tree.lowest[node]).min(0);
+                    tree.highest[src] = (delta + tree.highest[node]).max(0);
+                    stack.push((src, 0));
+                }
+                None => {
+                    tree.exit[node] = clock;
+                    clock += 1;
+                }
+            }
+        }
+        tree
+    }
+
+    fn is_ancestor(&self, a: usize, b: usize) -> bool {
+        self.terminates[a] && self.enter[a] <= self.enter[b] && self.exit[b] <= self.exit[a]
+    }
+
+    /// The final accumulator when the run continues at `at` with `accumulator`, or `None` if it
+    /// overflows at some point.
+    fn finish(&self, at: usize, accumulator: i64) -> Option<i64> {
+        let accumulator = accumulator as i128;
+        let fits = |v: i128| (i64::MIN as i128..=i64::MAX as i128).contains(&v);
+        if fits(accumulator + self.lowest[at]) && fits(accumulator + self.highest[at]) {
+            Some((accumulator + self.suffix[at]) as i64)
+        } else {
+            None
+        }
+    }
+}
+
+fn acc_delta(program: &Program, at: usize) -> i64 {
+    match program.get_instr(at) {
+        Some(Instruction::Acc(i)) => *i,
+        _ => 0,
+    }
+}
+
+fn target(at: usize, offset: isize, len: usize) -> Option<usize> {
+    let t = (at as isize).saturating_add(offset);
+    if t < 0 || t as usize > len {
+        None
+    } else {
+        Some(t as usize)
+    }
+}
+
+impl RepairSearch {
+    pub fn new() -> Self {
+        RepairSearch { replace_acc: false }
+    }
+
+    /// Also tries replacing `acc x` with `nop x` and `jmp x`.
+    pub fn replace_acc(mut self, replace_acc: bool) -> Self {
+        self.replace_acc = replace_acc;
+        self
+    }
+
+    pub fn find_all(&self, program: &Program) -> RepairResult<Vec<Repair>> {
+        let n = program.len();
+        let successors = (0..n)
+            .map(|i| match program.get_instr(i).unwrap() {
+                Instruction::Nop(_) | Instruction::Acc(_) => Ok(Some(i + 1)),
+                Instruction::Jmp(o) => Ok(target(i, *o, n)),
+                Instruction::Custom(..) => Err(RepairError::UnsupportedInstruction(i)),
+            })
+            .collect::<RepairResult<Vec<_>>>()?;
+        let tree = Tree::build(&successors, program);
+
+        let mut repairs = Vec::new();
+        let mut visited = vec![false; n];
+        let mut current = Some(0);
+        let mut accumulator = 0;
+        while let Some(i) = current.filter(|i| *i < n && !visited[*i]) {
+            visited[i] = true;
+            for replacement in self.candidates(program.get_instr(i).unwrap()) {
+                let next = match replacement {
+                    Instruction::Jmp(o) => target(i, o, n),
+                    _ => Some(i + 1),
+                };
+                let result = next
+                    .filter(|&t| tree.terminates[t] && !tree.is_ancestor(i, t))
+                    .and_then(|t| tree.finish(t, accumulator));
+                if let Some(result) = result {
+                    repairs.push(Repair {
+                        index: i,
+                        replacement,
+                        accumulator: result,
+                    });
+                }
+            }
+            // Nothing after an overflow is reachable.
+            accumulator = match accumulator.checked_add(acc_delta(program, i)) {
+                Some(a) => a,
+                None => break,
+            };
+            current = successors[i];
+        }
+        repairs.sort_by_key(|r| r.index);
+        Ok(repairs)
+    }
+
+    fn candidates(&self, instr: &Instruction) -> Vec<Instruction> {
+        match instr {
+            Instruction::Nop(i) => vec![Instruction::Jmp(*i)],
+            Instruction::Jmp(i) => vec![Instruction::Nop(*i)],
+            Instruction::Acc(i) if self.replace_acc => {
+                vec![Instruction::Nop(*i as isize), Instruction::Jmp(*i as isize)]
+            }
+            _ => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{Halt, Observer, ProgramState, VirtualMachine};
+
+    const EXAMPLE: [&str; 9] = [
+        "nop +0", "acc +1", "jmp +4", "acc +3", "jmp -3", "acc -99", "acc +1", "jmp -4", "acc +6",
+    ];
+
+    #[test]
+    fn repairs_example() {
+        let p = Program::parse_lines(&EXAMPLE).unwrap();
+        assert_eq!(
+            Ok(vec![Repair {
+                index: 7,
+                replacement: Instruction::Nop(-4),
+                accumulator: 8
+            }]),
+            RepairSearch::new().find_all(&p)
+        );
+    }
+
+    #[test]
+    fn repairs_acc() {
+        let p = Program::parse_lines(&["acc +2", "jmp +0"]).unwrap();
+        assert_eq!(
+            Ok(vec![
+                Repair {
+                    index: 0,
+                    replacement: Instruction::Jmp(2),
+                    accumulator: 0
+                },
+                Repair {
+                    index: 1,
+                    replacement: Instruction::Nop(0),
+                    accumulator: 2
+                }
+            ]),
+            RepairSearch::new().replace_acc(true).find_all(&p)
+        );
+    }
+
+    #[test]
+    fn rejects_patches_that_loop_back() {
+        // Flipping the last instruction jumps back into a path that leads to it again.
+        let p = Program::parse_lines(&["jmp +2", "nop -1", "nop -1"]).unwrap();
+        let repairs = RepairSearch::new().find_all(&p).unwrap();
+        assert_eq!(vec![0], repairs.iter().map(|r| r.index).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn rejects_patches_that_overflow() {
+        let mut lines = [
+            "acc +9223372036854775807",
+            "nop +2",
+            "jmp +0",
+            "acc +1",
+            "acc -1",
+        ];
+        let mut p = Program::parse_lines(&lines).unwrap();
+        assert_eq!(Ok(vec![]), RepairSearch::new().find_all(&p));
+        assert_eq!(Vec::<Repair>::new(), brute_force(&mut p, true));
+
+        lines.swap(3, 4);
+        let mut p = Program::parse_lines(&lines).unwrap();
+        let repairs = RepairSearch::new().replace_acc(true).find_all(&p).unwrap();
+        assert_eq!(
+            vec![(1, i64::MAX), (2, i64::MAX)],
+            repairs
+                .iter()
+                .map(|r| (r.index, r.accumulator))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(repairs, brute_force(&mut p, true));
+
+        // The accumulator overflows before the loop is reached.
+        let mut p =
+            Program::parse_lines(&["acc +9223372036854775807", "acc +1", "jmp +0"]).unwrap();
+        assert_eq!(Ok(vec![]), RepairSearch::new().find_all(&p));
+        assert_eq!(Vec::<Repair>::new(), brute_force(&mut p, false));
+    }
+
+    #[test]
+    fn rejects_custom_instructions() {
+        let mut p = Program::parse_lines(&["nop +0", "nop +0"]).unwrap();
+        p.set_instr(1, Instruction::Custom(&Halt, 0));
+        assert_eq!(
+            Err(RepairError::UnsupportedInstruction(1)),
+            RepairSearch::new().find_all(&p)
+        );
+    }
+
+    struct Executed(usize, bool);
+
+    impl Observer for Executed {
+        fn before_step(&mut self, state: &ProgramState, _: &Instruction) {
+            self.1 |= state.instruction == self.0;
+        }
+    }
+
+    fn brute_force(program: &mut Program, replace_acc: bool) -> Vec<Repair> {
+        let search = RepairSearch::new().replace_acc(replace_acc);
+        let mut repairs = Vec::new();
+        for i in 0..program.len() {
+            let original = *program.get_instr(i).unwrap();
+            for replacement in search.candidates(&original) {
+                program.set_instr(i, replacement);
+                let mut executed = Executed(i, false);
+                let result = {
+                    let mut vm = VirtualMachine::new(program);
+                    vm.observe(&mut executed);
+                    vm.execute()
+                };
+                if let (Ok(accumulator), true) = (result, executed.1) {
+                    repairs.push(Repair {
+                        index: i,
+                        replacement,
+                        accumulator,
+                    });
+                }
+            }
+            program.set_instr(i, original);
+        }
+        repairs
+    }
+
+    fn check(lines: &[&str]) {
+        let mut p = Program::parse_lines(lines).unwrap();
+        for &replace_acc in &[false, true] {
+            assert_eq!(
+                Ok(brute_force(&mut p, replace_acc)),
+                RepairSearch::new().replace_acc(replace_acc).find_all(&p),
+                "{:?}",
+                lines
+            );
+        }
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        check(&["nop +0", "jmp +9223372036854775807"]);
+        check(&["jmp +1", "nop -9223372036854775808"]);
+        let mut seed: u64 = 0x2020;
+        let mut next = |m: u64| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) % m
+        };
+        for _ in 0..500 {
+            let len = 1 + next(12) as usize;
+            let lines: Vec<String> = (0..len)
+                .map(|_| {
+                    let arg = next(9) as i64 - 4;
+                    match next(3) {
+                        0 => format!("nop {:+}", arg),
+                        1 => format!("acc {:+}", arg),
+                        _ => format!("jmp {:+}", arg),
+                    }
+                })
+                .collect();
+            let lines: Vec<&str> = lines.iter().map(String::as_ref).collect();
+            check(&lines);
+        }
+    }
+}
@@ -0,0 +1,383 @@
+use std::{
+    fmt::Write as _,
+    fs,
+    io::{self, BufRead, Write},
+};
+
+use super::{
+    no_args, parse_arg, run_session, CodeParseError, CommandError, CommandResult, Diagnostic,
+    ExecutionLimits, Instruction, InstructionSet, MachineProfile, Program, ProgramState,
+    VirtualMachine,
+};
+
+const HELP: &str = "\
+<instruction>    append an instruction to the program
+:run             (:r) run until termination or an error
+:step [n]        (:s) execute n instructions (default 1)
+:reset           restart from the first instruction
+:state           print the program state
+:list            (:l) show the program
+:pop             remove the last instruction
+:clear           remove all instructions
+:load <file>     replace the program with the one in the file
+:save <file>     write the program to the file
+:help            (:h) show this help
+:quit            (:q) exit
+";
+
+/// An interactive session that builds a program one instruction at a time and runs it.
+pub struct Repl {
+    set: InstructionSet,
+    profile: MachineProfile,
+    program: Program,
+    state: ProgramState,
+}
+
+impl Repl {
+    pub const PROMPT: &'static str = "> ";
+
+    pub fn new(set: InstructionSet) -> Self {
+        Repl {
+            set,
+            profile: MachineProfile::new(),
+            program: Program::from(Vec::new()),
+            state: ProgramState::default(),
+        }
+    }
+
+    /// Runs programs with the registers and memory of `profile`.
+    pub fn with_profile(mut self, profile: MachineProfile) -> Self {
+        self.state = profile.initial_state();
+        self.profile = profile;
+        self
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    pub fn state(&self) -> &ProgramState {
+        &self.state
+    }
+
+    /// Reads lines from `input` until it is exhausted or `:quit` is entered. With `echo` set,
+    /// every line is written after the prompt.
+    pub fn run<R: BufRead, W: Write>(&mut self, input: R, out: W, echo: bool) -> io::Result<()> {
+        run_session(input, out, Self::PROMPT, echo, |line| self.execute(line))
+    }
+
+    /// Appends an instruction or executes a `:` command, returning the output or `None` if the
+    /// session should end.
+    pub fn execute(&mut self, line: &str) -> Option<CommandResult<String>> {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            return Some(Ok(String::new()));
+        }
+        if !trimmed.starts_with(':') {
+            return Some(Ok(self.append(line)));
+        }
+
+        let mut parts = trimmed[1..].split_whitespace();
+        let command = parts.next().unwrap_or_default();
+        let args: Vec<_> = parts.collect();
+        Some(match command {
+            "r" | "run" => self.run_program(&args),
+            "s" | "step" => self.step(&args),
+            "reset" => self.reset(&args),
+            "state" => no_args("state", &args).map(|_| self.describe()),
+            "l" | "list" => self.list(&args),
+            "pop" => self.pop(&args),
+            "clear" => self.clear(&args),
+            "load" => self.load(&args),
+            "save" => self.save(&args),
+            "h" | "help" => Ok(HELP.to_string()),
+            "q" | "quit" => return None,
+            x => Err(CommandError::UnknownCommand(format!(":{}", x))),
+        })
+    }
+
+    fn append(&mut self, line: &str) -> String {
+        let at = self.program.len();
+        match Instruction::parse_located(line, &self.set) {
+            Ok(instr) => {
+                self.program.push(instr);
+                format!("{:>4}: {}\n", at, instr)
+            }
+            Err((range, e)) => Diagnostic::new(at + 1, line, range, e).to_string(),
+        }
+    }
+
+    fn run_program(&mut self, args: &[&str]) -> CommandResult<String> {
+        no_args("run", args)?;
+        let mut vm =
+            VirtualMachine::with_state(&self.program, ExecutionLimits::new(), self.state.clone());
+        let result = vm.execute();
+        self.state = vm.current_state().clone();
+        Ok(match result {
+            Ok(_) => self.describe(),
+            Err(e) => format!("Execution failed: {}\n{}", e, self.describe()),
+        })
+    }
+
+    fn step(&mut self, args: &[&str]) -> CommandResult<String> {
+        let count = match args {
+            [] => 1,
+            [n] => parse_arg("step", n)?,
+            _ => return Err(CommandError::TooManyArguments("step".to_string())),
+        };
+        // Stepping is driven by hand, so going around a loop is not an error here.
+        let mut vm = VirtualMachine::with_state(
+            &self.program,
+            ExecutionLimits::unlimited(),
+            self.state.clone(),
+        );
+        let mut result = Ok(());
+        for _ in 0..count {
+            if vm.terminated() {
+                break;
+            }
+            result = vm.execute_one();
+            if result.is_err() {
+                break;
+            }
+        }
+        self.state = vm.current_state().clone();
+        Ok(match result {
+            Ok(_) => self.describe(),
+            Err(e) => format!("Execution failed: {}\n{}", e, self.describe()),
+        })
+    }
+
+    fn reset(&mut self, args: &[&str]) -> CommandResult<String> {
+        no_args("reset", args)?;
+        self.state = self.profile.initial_state();
+        Ok(self.describe())
+    }
+
+    fn list(&self, args: &[&str]) -> CommandResult<String> {
+        no_args("list", args)?;
+        let mut s = String::new();
+        for (i, instr) in self.program.instructions().iter().enumerate() {
+            let marker = if i == self.state.instruction {
+                "=>"
+            } else {
+                "  "
+            };
+            writeln!(s, "{} {:>4}: {}", marker, i, instr).unwrap();
+        }
+        if self.program.len() == 0 {
+            s.push_str("The program is empty\n");
+        }
+        Ok(s)
+    }
+
+    fn pop(&mut self, args: &[&str]) -> CommandResult<String> {
+        no_args("pop", args)?;
+        if self.program.len() == 0 {
+            return Ok("The program is empty\n".to_string());
+        }
+        let removed = self.program.remove(self.program.len() - 1);
+        self.state.instruction = self.state.instruction.min(self.program.len());
+        Ok(format!("Removed {}\n", removed))
+    }
+
+    fn clear(&mut self, args: &[&str]) -> CommandResult<String> {
+        no_args("clear", args)?;
+        self.program = Program::from(Vec::new());
+        self.state = self.profile.initial_state();
+        Ok(self.describe())
+    }
+
+    fn load(&mut self, args: &[&str]) -> CommandResult<String> {
+        let path = single_arg("load", args)?;
+        match Program::parse_file_all_with(path, &self.set) {
+            Ok(program) => {
+                self.program = program;
+                self.state = self.profile.initial_state();
+                Ok(format!(
+                    "Loaded {} instructions\n{}",
+                    self.program.len(),
+                    self.describe()
+                ))
+            }
+            Err(CodeParseError::IOError { error, .. }) => {
+                Err(CommandError::Io(path.to_string(), error.to_string()))
+            }
+            Err(e) => Ok(format!("{}\n", e)),
+        }
+    }
+
+    fn save(&self, args: &[&str]) -> CommandResult<String> {
+        let path = single_arg("save", args)?;
+        fs::write(path, self.program.to_string())
+            .map_err(|e| CommandError::Io(path.to_string(), e.to_string()))?;
+        Ok(format!(
+            "Saved {} instructions to {}\n",
+            self.program.len(),
+            path
+        ))
+    }
+
+    fn describe(&self) -> String {
+        let terminated = if self.state.instruction == self.program.len() {
+            " (end)"
+        } else {
+            ""
+        };
+        format!("{}{}\n", self.state, terminated)
+    }
+}
+
+fn single_arg<'s>(command: &str, args: &[&'s str]) -> Result<&'s str, CommandError> {
+    match args {
+        [] => Err(CommandError::MissingArgument(command.to_string())),
+        [arg] => Ok(arg),
+        _ => Err(CommandError::TooManyArguments(command.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transcript(repl: &mut Repl, script: &[&str]) -> Vec<String> {
+        let mut out = Vec::new();
+        let script = script.join("\n");
+        repl.run(script.as_bytes(), &mut out, true).unwrap();
+        String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn builds_and_runs() {
+        let mut repl = Repl::new(InstructionSet::new());
+        let out = transcript(
+            &mut repl,
+            &[
+                "acc +3", ":step", "jmp +2", "acc +1", ":run", ":list", ":reset",
+            ],
+        );
+        assert_eq!(
+            vec![
+                "> acc +3",
+                "   0: acc +3",
+                "> :step",
+                "@1 acc=3 (end)",
+                "> jmp +2",
+                "   1: jmp +2",
+                "> acc +1",
+                "   2: acc +1",
+                "> :run",
+                "@3 acc=3 (end)",
+                "> :list",
+                "      0: acc +3",
+                "      1: jmp +2",
+                "      2: acc +1",
+                "> :reset",
+                "@0 acc=0",
+                "> ",
+            ],
+            out
+        );
+    }
+
+    #[test]
+    fn reports_parse_errors_inline() {
+        let mut repl = Repl::new(InstructionSet::new());
+        let out = transcript(&mut repl, &["nop +0", "acc x1", ":list"]);
+        assert_eq!(
+            vec![
+                "> nop +0",
+                "   0: nop +0",
+                "> acc x1",
+                "error: Unparseable parameter x1 for instruction acc",
+                " --> 2:5",
+                "  |",
+                "2 | acc x1",
+                "  |     ^^",
+                "> :list",
+                "=>    0: nop +0",
+                "> ",
+            ],
+            out
+        );
+    }
+
+    #[test]
+    fn execution_errors() {
+        let mut repl = Repl::new(InstructionSet::new());
+        for line in &["acc +1", "jmp -1"] {
+            repl.execute(line);
+        }
+        assert_eq!(Some(Ok("@0 acc=2\n".to_string())), repl.execute(":step 4"));
+        assert_eq!(
+            Some(Ok(
+                "Execution failed: Infinite loop detected at instruction 0\n@1 acc=3\n".to_string()
+            )),
+            repl.execute(":run")
+        );
+        assert_eq!(
+            Some(Ok("Removed jmp -1\n".to_string())),
+            repl.execute(":pop")
+        );
+        assert_eq!(
+            Some(Ok("@1 acc=3 (end)\n".to_string())),
+            repl.execute(":state")
+        );
+    }
+
+    #[test]
+    fn uses_profile() {
+        let profile = MachineProfile::new().register("a");
+        let mut repl = Repl::new(InstructionSet::extended()).with_profile(profile);
+        for line in &["acc +4", "st a", "addr a"] {
+            repl.execute(line);
+        }
+        assert_eq!(
+            Some(Ok("@3 acc=8 a=4 (end)\n".to_string())),
+            repl.execute(":run")
+        );
+        assert_eq!(
+            Some(Ok("@0 acc=0 a=0\n".to_string())),
+            repl.execute(":reset")
+        );
+    }
+
+    #[test]
+    fn saves_and_loads() {
+        let path = std::env::temp_dir().join("repl-saves-and-loads.txt");
+        let path = path.to_str().unwrap();
+        let mut repl = Repl::new(InstructionSet::new());
+        repl.execute("acc +7");
+        repl.execute("nop -1");
+        assert_eq!(
+            Some(Ok(format!("Saved 2 instructions to {}\n", path))),
+            repl.execute(&format!(":save {}", path))
+        );
+
+        let mut other = Repl::new(InstructionSet::new());
+        assert_eq!(
+            Some(Ok("Loaded 2 instructions\n@0 acc=0\n".to_string())),
+            other.execute(&format!(":load {}", path))
+        );
+        assert_eq!(repl.program().to_string(), other.program().to_string());
+        std::fs::remove_file(path).unwrap();
+
+        assert!(matches!(
+            other.execute(":load this file does not exist.txt"),
+            Some(Err(CommandError::TooManyArguments(_)))
+        ));
+        assert!(matches!(
+            other.execute(":load missing.txt"),
+            Some(Err(CommandError::Io(..)))
+        ));
+        assert_eq!(
+            Some(Err(CommandError::UnknownCommand(":frobnicate".to_string()))),
+            other.execute(":frobnicate")
+        );
+        assert_eq!(None, other.execute(":quit"));
+    }
+}
@@ -0,0 +1,115 @@
+use super::{ExecutionResult, Flow, Instruction, Operation, Program, ProgramState};
+
+/// `tgl +n`: turns the `nop` `n` instructions away into a `jmp` with the same operand and the
+/// other way round. Any other instruction, or a target outside the program, is left alone.
+pub struct Toggle;
+
+impl Operation for Toggle {
+    fn mnemonic(&self) -> &str {
+        "tgl"
+    }
+
+    // No `successors`: the flow of the rest of the program changes as it runs, so static
+    // analyses and the optimizer must not rely on it.
+
+    fn execute(&self, _: i64, _: &mut ProgramState) -> ExecutionResult<Flow> {
+        Ok(Flow::Next)
+    }
+
+    fn modifies_program(&self) -> bool {
+        true
+    }
+
+    fn modify_program(
+        &self,
+        operand: i64,
+        state: &ProgramState,
+        program: &Program,
+    ) -> ExecutionResult<Option<Program>> {
+        let target = (state.instruction as i64).saturating_add(operand);
+        if target < 0 {
+            return Ok(None);
+        }
+        let target = target as usize;
+        let toggled = match program.get_instr(target) {
+            Some(Instruction::Nop(i)) => Instruction::Jmp(*i),
+            Some(Instruction::Jmp(i)) => Instruction::Nop(*i),
+            _ => return Ok(None),
+        };
+        let mut program = program.clone();
+        program.set_instr(target, toggled);
+        Ok(Some(program))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{
+        CompiledMachine, CompiledProgram, ExecutionError, ExecutionLimits, InstructionSet,
+        LoopDetection, Machine, VirtualMachine,
+    };
+
+    fn program(lines: &[&str]) -> Program {
+        Program::parse_lines_with(lines, &InstructionSet::extended()).unwrap()
+    }
+
+    #[test]
+    fn toggles_nop_and_jmp() {
+        // The first pass takes `jmp +2` and turns it into a `nop`, so the second pass exits.
+        let p = program(&["acc +1", "jmp +2", "jmp +3", "tgl -2", "jmp -4", "acc +10"]);
+        let mut vm = VirtualMachine::self_modifying(p.clone(), ExecutionLimits::new());
+        assert_eq!(Ok(12), vm.execute());
+        assert_eq!("nop +2", vm.program().get_instr(1).unwrap().to_string());
+        assert_eq!("jmp +2", p.get_instr(1).unwrap().to_string());
+
+        // Toggling outside the program or something other than nop/jmp does nothing.
+        let p = program(&["tgl -5", "tgl +9", "tgl +1", "acc +1"]);
+        let mut vm = VirtualMachine::self_modifying(p, ExecutionLimits::new());
+        assert_eq!(Ok(1), vm.execute());
+    }
+
+    #[test]
+    fn loop_detection_sees_program_changes() {
+        // Every pass toggles instruction 1, so the loop only repeats on the third visit to 0.
+        let p = program(&["tgl +1", "nop +1", "jmp -2"]);
+        let mut vm = VirtualMachine::self_modifying(p.clone(), ExecutionLimits::new());
+        assert_eq!(Err(ExecutionError::InfiniteLoop(0)), vm.execute());
+        assert_eq!(5, vm.steps());
+
+        let limits = ExecutionLimits::new().loop_detection(LoopDetection::State);
+        let mut vm = VirtualMachine::self_modifying(p, limits);
+        assert_eq!(Err(ExecutionError::RepeatedState(0, 0)), vm.execute());
+        assert_eq!(5, vm.steps());
+    }
+
+    #[test]
+    fn requires_an_owned_program() {
+        let p = program(&["acc +1", "tgl +1", "nop +0"]);
+        assert_eq!(
+            Err(ExecutionError::ReadOnlyProgram(1)),
+            VirtualMachine::new(&p).execute()
+        );
+        let c = CompiledProgram::new(&p);
+        assert_eq!(
+            Err(ExecutionError::ReadOnlyProgram(1)),
+            CompiledMachine::new(&c).execute()
+        );
+    }
+
+    #[test]
+    fn steps_back_over_rewrites() {
+        let p = program(&["tgl +1", "jmp +2", "acc +1", "acc +2"]);
+        let mut vm = VirtualMachine::self_modifying(p, ExecutionLimits::new());
+        vm.keep_history(10);
+        let snapshot = vm.snapshot();
+        vm.execute_one().unwrap();
+        assert_eq!("nop +2", vm.program().get_instr(1).unwrap().to_string());
+        assert!(vm.step_back());
+        assert_eq!("jmp +2", vm.program().get_instr(1).unwrap().to_string());
+        assert_eq!(Ok(3), vm.execute());
+        vm.restore(&snapshot);
+        assert_eq!("jmp +2", vm.program().get_instr(1).unwrap().to_string());
+        assert_eq!(Ok(3), vm.execute());
+    }
+}
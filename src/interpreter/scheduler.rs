@@ -0,0 +1,238 @@
+use super::{ExecutionError, ProgramState, SchedulerError, SchedulerResult, VirtualMachine};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Schedule {
+    /// Gives each machine at most this many steps per round.
+    RoundRobin(usize),
+    /// Runs each machine until it blocks or terminates.
+    UntilBlocked,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MachineStatus {
+    Ready,
+    Blocked,
+    Terminated,
+}
+
+/// Runs several machines cooperatively, passing the values each one sends to the machine it is
+/// connected to. Values sent by an unconnected machine stay in its mailbox.
+pub struct Scheduler<'a> {
+    machines: Vec<VirtualMachine<'a>>,
+    status: Vec<MachineStatus>,
+    routes: Vec<Option<usize>>,
+    schedule: Schedule,
+    rounds: usize,
+}
+
+impl<'a> Scheduler<'a> {
+    pub fn new(schedule: Schedule) -> Self {
+        if schedule == Schedule::RoundRobin(0) {
+            panic!("a round-robin quantum must be positive");
+        }
+        Scheduler {
+            machines: Vec::new(),
+            status: Vec::new(),
+            routes: Vec::new(),
+            schedule,
+            rounds: 0,
+        }
+    }
+
+    /// Adds a machine and returns its id.
+    pub fn add(&mut self, vm: VirtualMachine<'a>) -> usize {
+        self.machines.push(vm);
+        self.status.push(MachineStatus::Ready);
+        self.routes.push(None);
+        self.machines.len() - 1
+    }
+
+    /// Delivers everything `from` sends to `to`.
+    pub fn connect(&mut self, from: usize, to: usize) {
+        if to >= self.machines.len() {
+            panic!("there is no machine {}", to);
+        }
+        self.routes[from] = Some(to);
+    }
+
+    pub fn len(&self) -> usize {
+        self.machines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.machines.is_empty()
+    }
+
+    pub fn machine(&self, id: usize) -> &VirtualMachine<'a> {
+        &self.machines[id]
+    }
+
+    pub fn machine_mut(&mut self, id: usize) -> &mut VirtualMachine<'a> {
+        &mut self.machines[id]
+    }
+
+    pub fn state(&self, id: usize) -> &ProgramState {
+        self.machines[id].current_state()
+    }
+
+    pub fn status(&self, id: usize) -> MachineStatus {
+        self.status[id]
+    }
+
+    pub fn rounds(&self) -> usize {
+        self.rounds
+    }
+
+    /// Gives every machine one turn and returns whether all of them have terminated.
+    pub fn round(&mut self) -> SchedulerResult<bool> {
+        let mut progress = false;
+        for id in 0..self.machines.len() {
+            progress |= self.turn(id)?;
+            if let Some(to) = self.routes[id] {
+                while let Some(value) = self.machines[id].receive() {
+                    self.machines[to].send(value);
+                }
+            }
+        }
+        self.rounds += 1;
+
+        if self.status.iter().all(|s| *s == MachineStatus::Terminated) {
+            Ok(true)
+        } else if progress {
+            Ok(false)
+        } else {
+            let blocked = (0..self.machines.len())
+                .filter(|id| self.status[*id] == MachineStatus::Blocked)
+                .collect();
+            Err(SchedulerError::Deadlock(blocked))
+        }
+    }
+
+    /// Runs rounds until every machine terminates.
+    pub fn run(&mut self) -> SchedulerResult<()> {
+        while !self.round()? {}
+        Ok(())
+    }
+
+    /// Runs a single machine for its share of the round, returning whether it made any progress.
+    fn turn(&mut self, id: usize) -> SchedulerResult<bool> {
+        let vm = &mut self.machines[id];
+        let mut steps = 0;
+        let status = loop {
+            if vm.terminated() {
+                break MachineStatus::Terminated;
+            }
+            if self.schedule == Schedule::RoundRobin(steps) {
+                break MachineStatus::Ready;
+            }
+            match vm.execute_one() {
+                Ok(()) => steps += 1,
+                Err(ExecutionError::Blocked(_)) => break MachineStatus::Blocked,
+                Err(e) => return Err(SchedulerError::Failed(id, e)),
+            }
+        };
+        self.status[id] = status;
+        Ok(steps > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{ExecutionLimits, InstructionSet, Program};
+
+    fn parse(lines: &[&str]) -> Program {
+        Program::parse_lines_with(lines, &InstructionSet::extended()).unwrap()
+    }
+
+    #[test]
+    fn runs_a_pipeline() {
+        let a = parse(&["acc +5", "snd"]);
+        let b = parse(&["rcv", "mul +2", "snd"]);
+        let c = parse(&["rcv", "acc +1"]);
+
+        let mut s = Scheduler::new(Schedule::RoundRobin(1));
+        for p in &[&a, &b, &c] {
+            s.add(VirtualMachine::new(p));
+        }
+        s.connect(0, 1);
+        s.connect(1, 2);
+        assert_eq!(Ok(false), s.round());
+        assert_eq!(
+            &ProgramState {
+                instruction: 1,
+                accumulator: 5,
+                ..ProgramState::default()
+            },
+            s.state(0)
+        );
+        assert_eq!(MachineStatus::Ready, s.status(0));
+        assert_eq!(MachineStatus::Blocked, s.status(1));
+        assert_eq!(Ok(()), s.run());
+        assert_eq!(5, s.rounds());
+        assert_eq!(11, s.state(2).accumulator());
+
+        let mut s = Scheduler::new(Schedule::UntilBlocked);
+        for p in &[&a, &b, &c] {
+            s.add(VirtualMachine::new(p));
+        }
+        s.connect(0, 1);
+        s.connect(1, 2);
+        assert_eq!(Ok(()), s.run());
+        assert_eq!(1, s.rounds());
+        assert_eq!(11, s.state(2).accumulator());
+    }
+
+    #[test]
+    fn plays_ping_pong() {
+        let ping = parse(&["acc +3", "snd", "rcv", "acc -1", "jnz -3"]);
+        let echo = parse(&["rcv", "snd", "jmp -2"]);
+        let mut s = Scheduler::new(Schedule::RoundRobin(2));
+        s.add(VirtualMachine::with_limits(
+            &ping,
+            ExecutionLimits::unlimited(),
+        ));
+        s.add(VirtualMachine::with_limits(
+            &echo,
+            ExecutionLimits::unlimited(),
+        ));
+        s.connect(0, 1);
+        s.connect(1, 0);
+        assert_eq!(Err(SchedulerError::Deadlock(vec![1])), s.run());
+        assert_eq!(MachineStatus::Terminated, s.status(0));
+        assert_eq!(0, s.state(0).accumulator());
+        assert_eq!(
+            &ProgramState {
+                instruction: 0,
+                accumulator: 1,
+                ..ProgramState::default()
+            },
+            s.state(1)
+        );
+    }
+
+    #[test]
+    fn reports_deadlocks_and_failures() {
+        let p = parse(&["rcv", "snd"]);
+        let mut s = Scheduler::new(Schedule::UntilBlocked);
+        s.add(VirtualMachine::new(&p));
+        s.add(VirtualMachine::new(&p));
+        s.connect(0, 1);
+        s.connect(1, 0);
+        assert_eq!(Err(SchedulerError::Deadlock(vec![0, 1])), s.round());
+
+        // Sending a value in from outside unblocks the cycle.
+        s.machine_mut(0).send(7);
+        assert_eq!(Ok(()), s.run());
+        assert_eq!(7, s.state(1).accumulator());
+        assert_eq!(vec![7], Vec::from(s.machine(0).mailbox().inbox.clone()));
+
+        let bad = parse(&["snd", "jmp -4"]);
+        let mut s = Scheduler::new(Schedule::UntilBlocked);
+        s.add(VirtualMachine::new(&bad));
+        assert_eq!(
+            Err(SchedulerError::Failed(0, ExecutionError::InvalidAccess(-3))),
+            s.run()
+        );
+    }
+}
@@ -0,0 +1,523 @@
+use std::{collections::BTreeMap, fmt, sync::Arc};
+
+use super::{
+    Arithmetic, ExecutionError, ExecutionResult, Flow, Operation, ParseError, ParseResult,
+    ProgramState,
+};
+
+const MAX_REGISTER_NAME: usize = 8;
+
+/// Packs a register name of up to eight lowercase letters, digits or underscores, starting with
+/// a letter, into an instruction operand.
+pub const fn encode_register(name: &str) -> Option<i64> {
+    let bytes = name.as_bytes();
+    if bytes.is_empty() || bytes.len() > MAX_REGISTER_NAME || !bytes[0].is_ascii_lowercase() {
+        return None;
+    }
+    let mut packed = [0; MAX_REGISTER_NAME];
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        if !(b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_') {
+            return None;
+        }
+        packed[i] = b;
+        i += 1;
+    }
+    Some(i64::from_le_bytes(packed))
+}
+
+pub fn decode_register(operand: i64) -> String {
+    operand
+        .to_le_bytes()
+        .iter()
+        .take_while(|b| **b != 0)
+        .map(|b| *b as char)
+        .collect()
+}
+
+/// The named registers of a machine. Only the registers declared by its `MachineProfile` exist.
+/// Clones share their contents until one of them is written to.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Registers(Arc<BTreeMap<i64, i64>>);
+
+impl Registers {
+    pub fn get(&self, name: &str) -> Option<i64> {
+        encode_register(name).and_then(|r| self.0.get(&r).copied())
+    }
+
+    /// Returns false if there is no such register.
+    pub fn set(&mut self, name: &str, value: i64) -> bool {
+        encode_register(name).is_some_and(|r| self.write(r, value).is_ok())
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Registers and their values, sorted by packed name.
+    pub fn iter(&self) -> impl Iterator<Item = (String, i64)> + '_ {
+        self.0.iter().map(|(r, v)| (decode_register(*r), *v))
+    }
+
+    pub(super) fn read(&self, register: i64) -> ExecutionResult<i64> {
+        self.0
+            .get(&register)
+            .copied()
+            .ok_or_else(|| ExecutionError::UnknownRegister(decode_register(register)))
+    }
+
+    pub(super) fn write(&mut self, register: i64, value: i64) -> ExecutionResult<()> {
+        if !self.0.contains_key(&register) {
+            return Err(ExecutionError::UnknownRegister(decode_register(register)));
+        }
+        Arc::make_mut(&mut self.0).insert(register, value);
+        Ok(())
+    }
+}
+
+/// Memory cells at addresses `0..size`, all zero until written. Only non-zero cells are stored,
+/// and clones share them until one of them is written to.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Memory {
+    size: i64,
+    cells: Arc<BTreeMap<i64, i64>>,
+}
+
+impl Memory {
+    pub fn new(size: i64) -> Self {
+        Memory {
+            size: size.max(0),
+            cells: Arc::default(),
+        }
+    }
+
+    pub fn size(&self) -> i64 {
+        self.size
+    }
+
+    /// Returns `None` if `address` is out of range.
+    pub fn get(&self, address: i64) -> Option<i64> {
+        self.load(address).ok()
+    }
+
+    /// Returns false if `address` is out of range.
+    pub fn set(&mut self, address: i64, value: i64) -> bool {
+        self.store(address, value).is_ok()
+    }
+
+    /// The non-zero cells in address order.
+    pub fn iter(&self) -> impl Iterator<Item = (i64, i64)> + '_ {
+        self.cells.iter().map(|(a, v)| (*a, *v))
+    }
+
+    pub(super) fn load(&self, address: i64) -> ExecutionResult<i64> {
+        if address < 0 || address >= self.size {
+            return Err(ExecutionError::InvalidAddress(address));
+        }
+        Ok(self.cells.get(&address).copied().unwrap_or(0))
+    }
+
+    pub(super) fn store(&mut self, address: i64, value: i64) -> ExecutionResult<()> {
+        if address < 0 || address >= self.size {
+            return Err(ExecutionError::InvalidAddress(address));
+        }
+        // Zero cells are never stored, so that equal memories compare and hash the same.
+        let cells = Arc::make_mut(&mut self.cells);
+        if value == 0 {
+            cells.remove(&address);
+        } else {
+            cells.insert(address, value);
+        }
+        Ok(())
+    }
+}
+
+/// The registers, memory and arithmetic a machine starts with. The default profile has no
+/// registers or memory and checks for overflow, which is the plain single-accumulator machine.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MachineProfile {
+    registers: Vec<String>,
+    memory_size: i64,
+    arithmetic: Arithmetic,
+}
+
+impl MachineProfile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(mut self, name: &str) -> Self {
+        if encode_register(name).is_none() {
+            panic!("invalid register name {}", name);
+        }
+        if self.registers.iter().any(|r| r == name) {
+            panic!("register {} is already defined", name);
+        }
+        self.registers.push(name.to_string());
+        self
+    }
+
+    pub fn memory(mut self, size: i64) -> Self {
+        self.memory_size = size;
+        self
+    }
+
+    pub fn arithmetic(mut self, arithmetic: Arithmetic) -> Self {
+        self.arithmetic = arithmetic;
+        self
+    }
+
+    pub fn registers(&self) -> &[String] {
+        &self.registers
+    }
+
+    pub fn memory_size(&self) -> i64 {
+        self.memory_size
+    }
+
+    pub fn arithmetic_mode(&self) -> Arithmetic {
+        self.arithmetic
+    }
+
+    /// The state at the start of a program: every register and memory cell is zero.
+    pub fn initial_state(&self) -> ProgramState {
+        let registers = self
+            .registers
+            .iter()
+            .filter_map(|r| encode_register(r))
+            .map(|r| (r, 0))
+            .collect();
+        ProgramState {
+            registers: Registers(Arc::new(registers)),
+            memory: Memory::new(self.memory_size),
+            arithmetic: self.arithmetic,
+            ..ProgramState::default()
+        }
+    }
+}
+
+impl fmt::Display for ProgramState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "@{} acc={}", self.instruction, self.exact_accumulator())?;
+        for (name, value) in self.registers.iter() {
+            write!(f, " {}={}", name, value)?;
+        }
+        for (address, value) in self.memory.iter() {
+            write!(f, " [{}]={}", address, value)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_register(instr: &str, s: &str) -> ParseResult<i64> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Err(ParseError::MissingParameter(instr.to_string()));
+    }
+    encode_register(s)
+        .ok_or_else(|| ParseError::UnparseableParameter(instr.to_string(), s.to_string()))
+}
+
+/// `ld r`: copies register `r` into the accumulator.
+pub struct Load;
+
+impl Operation for Load {
+    fn mnemonic(&self) -> &str {
+        "ld"
+    }
+
+    fn parse_operand(&self, s: &str) -> ParseResult<i64> {
+        parse_register(self.mnemonic(), s)
+    }
+
+    fn format_operand(&self, operand: i64) -> Option<String> {
+        Some(decode_register(operand))
+    }
+
+    fn successors(&self, _: i64) -> Option<Vec<Flow>> {
+        Some(vec![Flow::Next])
+    }
+
+    fn execute(&self, operand: i64, state: &mut ProgramState) -> ExecutionResult<Flow> {
+        state.set_accumulator(state.registers.read(operand)?);
+        Ok(Flow::Next)
+    }
+}
+
+/// `st r`: copies the accumulator into register `r`.
+pub struct Store;
+
+impl Operation for Store {
+    fn mnemonic(&self) -> &str {
+        "st"
+    }
+
+    fn parse_operand(&self, s: &str) -> ParseResult<i64> {
+        parse_register(self.mnemonic(), s)
+    }
+
+    fn format_operand(&self, operand: i64) -> Option<String> {
+        Some(decode_register(operand))
+    }
+
+    fn successors(&self, _: i64) -> Option<Vec<Flow>> {
+        Some(vec![Flow::Next])
+    }
+
+    fn execute(&self, operand: i64, state: &mut ProgramState) -> ExecutionResult<Flow> {
+        state.registers.write(operand, state.accumulator())?;
+        Ok(Flow::Next)
+    }
+}
+
+/// `addr r`: adds register `r` to the accumulator.
+pub struct AddRegister;
+
+impl Operation for AddRegister {
+    fn mnemonic(&self) -> &str {
+        "addr"
+    }
+
+    fn parse_operand(&self, s: &str) -> ParseResult<i64> {
+        parse_register(self.mnemonic(), s)
+    }
+
+    fn format_operand(&self, operand: i64) -> Option<String> {
+        Some(decode_register(operand))
+    }
+
+    fn successors(&self, _: i64) -> Option<Vec<Flow>> {
+        Some(vec![Flow::Next])
+    }
+
+    fn execute(&self, operand: i64, state: &mut ProgramState) -> ExecutionResult<Flow> {
+        state.add_to_accumulator(state.registers.read(operand)?)?;
+        Ok(Flow::Next)
+    }
+}
+
+/// `subr r`: subtracts register `r` from the accumulator.
+pub struct SubRegister;
+
+impl Operation for SubRegister {
+    fn mnemonic(&self) -> &str {
+        "subr"
+    }
+
+    fn parse_operand(&self, s: &str) -> ParseResult<i64> {
+        parse_register(self.mnemonic(), s)
+    }
+
+    fn format_operand(&self, operand: i64) -> Option<String> {
+        Some(decode_register(operand))
+    }
+
+    fn successors(&self, _: i64) -> Option<Vec<Flow>> {
+        Some(vec![Flow::Next])
+    }
+
+    fn execute(&self, operand: i64, state: &mut ProgramState) -> ExecutionResult<Flow> {
+        state.subtract_from_accumulator(state.registers.read(operand)?)?;
+        Ok(Flow::Next)
+    }
+}
+
+/// `mulr r`: multiplies the accumulator by register `r`.
+pub struct MulRegister;
+
+impl Operation for MulRegister {
+    fn mnemonic(&self) -> &str {
+        "mulr"
+    }
+
+    fn parse_operand(&self, s: &str) -> ParseResult<i64> {
+        parse_register(self.mnemonic(), s)
+    }
+
+    fn format_operand(&self, operand: i64) -> Option<String> {
+        Some(decode_register(operand))
+    }
+
+    fn successors(&self, _: i64) -> Option<Vec<Flow>> {
+        Some(vec![Flow::Next])
+    }
+
+    fn execute(&self, operand: i64, state: &mut ProgramState) -> ExecutionResult<Flow> {
+        state.multiply_accumulator(state.registers.read(operand)?)?;
+        Ok(Flow::Next)
+    }
+}
+
+/// `ldm +a`: copies memory cell `a` into the accumulator.
+pub struct LoadMemory;
+
+impl Operation for LoadMemory {
+    fn mnemonic(&self) -> &str {
+        "ldm"
+    }
+
+    fn successors(&self, _: i64) -> Option<Vec<Flow>> {
+        Some(vec![Flow::Next])
+    }
+
+    fn execute(&self, operand: i64, state: &mut ProgramState) -> ExecutionResult<Flow> {
+        state.set_accumulator(state.memory.load(operand)?);
+        Ok(Flow::Next)
+    }
+}
+
+/// `stm +a`: copies the accumulator into memory cell `a`.
+pub struct StoreMemory;
+
+impl Operation for StoreMemory {
+    fn mnemonic(&self) -> &str {
+        "stm"
+    }
+
+    fn successors(&self, _: i64) -> Option<Vec<Flow>> {
+        Some(vec![Flow::Next])
+    }
+
+    fn execute(&self, operand: i64, state: &mut ProgramState) -> ExecutionResult<Flow> {
+        state.memory.store(operand, state.accumulator())?;
+        Ok(Flow::Next)
+    }
+}
+
+/// `ldi r`: copies the memory cell whose address is in register `r` into the accumulator.
+pub struct LoadIndirect;
+
+impl Operation for LoadIndirect {
+    fn mnemonic(&self) -> &str {
+        "ldi"
+    }
+
+    fn parse_operand(&self, s: &str) -> ParseResult<i64> {
+        parse_register(self.mnemonic(), s)
+    }
+
+    fn format_operand(&self, operand: i64) -> Option<String> {
+        Some(decode_register(operand))
+    }
+
+    fn successors(&self, _: i64) -> Option<Vec<Flow>> {
+        Some(vec![Flow::Next])
+    }
+
+    fn execute(&self, operand: i64, state: &mut ProgramState) -> ExecutionResult<Flow> {
+        let address = state.registers.read(operand)?;
+        state.set_accumulator(state.memory.load(address)?);
+        Ok(Flow::Next)
+    }
+}
+
+/// `sti r`: copies the accumulator into the memory cell whose address is in register `r`.
+pub struct StoreIndirect;
+
+impl Operation for StoreIndirect {
+    fn mnemonic(&self) -> &str {
+        "sti"
+    }
+
+    fn parse_operand(&self, s: &str) -> ParseResult<i64> {
+        parse_register(self.mnemonic(), s)
+    }
+
+    fn format_operand(&self, operand: i64) -> Option<String> {
+        Some(decode_register(operand))
+    }
+
+    fn successors(&self, _: i64) -> Option<Vec<Flow>> {
+        Some(vec![Flow::Next])
+    }
+
+    fn execute(&self, operand: i64, state: &mut ProgramState) -> ExecutionResult<Flow> {
+        let address = state.registers.read(operand)?;
+        state.memory.store(address, state.accumulator())?;
+        Ok(Flow::Next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{ExecutionLimits, InstructionSet, Program, VirtualMachine};
+
+    fn run(lines: &[&str], profile: &MachineProfile) -> (ExecutionResult<i64>, ProgramState) {
+        let p = Program::parse_lines_with(lines, &InstructionSet::extended()).unwrap();
+        let mut vm =
+            VirtualMachine::with_state(&p, ExecutionLimits::unlimited(), profile.initial_state());
+        (vm.execute(), vm.current_state().clone())
+    }
+
+    #[test]
+    fn register_names() {
+        let r = encode_register("count_1").unwrap();
+        assert_eq!("count_1", decode_register(r));
+        assert_eq!(None, encode_register(""));
+        assert_eq!(None, encode_register("1a"));
+        assert_eq!(None, encode_register("Acc"));
+        assert_eq!(None, encode_register("toolongname"));
+    }
+
+    #[test]
+    fn parses_and_formats_operands() {
+        let set = InstructionSet::extended();
+        let lines = ["ld a", "st total", "addr b", "ldm +3", "sti p"];
+        let p = Program::parse_lines_with(&lines, &set).unwrap();
+        assert_eq!("ld a\nst total\naddr b\nldm +3\nsti p\n", p.to_string());
+        let p = Program::parse_lines_with(&["ld 3"], &set);
+        assert!(p.is_err());
+    }
+
+    #[test]
+    fn registers_and_memory() {
+        let profile = MachineProfile::new().register("a").register("p").memory(5);
+        // Stores 5, 4, 3, 2, 1 at addresses 0..5 and sums them back through register a.
+        let lines = [
+            "acc +5", "sti p", "acc -1", "st a", "ld p", "acc +1", "st p", "ld a", "jnz -7",
+            "ldm +0", "st a", "ldm +3", "addr a", "st a", "ldm +1", "mulr a",
+        ];
+        let (result, state) = run(&lines, &profile);
+        assert_eq!(Ok(28), result);
+        assert_eq!(Some(7), state.registers.get("a"));
+        assert_eq!(
+            vec![(0, 5), (1, 4), (2, 3), (3, 2), (4, 1)],
+            state.memory.iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn default_profile_has_no_storage() {
+        let profile = MachineProfile::new();
+        assert_eq!(ProgramState::default(), profile.initial_state());
+        let (result, _) = run(&["acc +1", "st a"], &profile);
+        assert_eq!(
+            Err(ExecutionError::UnknownRegister("a".to_string())),
+            result
+        );
+        let (result, _) = run(&["stm +0"], &profile);
+        assert_eq!(Err(ExecutionError::InvalidAddress(0)), result);
+
+        let profile = MachineProfile::new().memory(2);
+        let (result, _) = run(&["ldm -1"], &profile);
+        assert_eq!(Err(ExecutionError::InvalidAddress(-1)), result);
+    }
+
+    #[test]
+    fn zero_cells_are_not_stored() {
+        let mut a = Memory::new(10);
+        let b = a.clone();
+        assert!(a.set(3, 7));
+        assert_ne!(a, b);
+        assert!(a.set(3, 0));
+        assert_eq!(a, b);
+        assert!(!a.set(10, 1));
+        assert_eq!(Some(0), a.get(9));
+    }
+}
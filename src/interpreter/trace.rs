@@ -0,0 +1,185 @@
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use super::{ExecutionError, Observer, ProgramState, Step};
+
+#[derive(Serialize)]
+struct TraceRecord {
+    step: usize,
+    instruction: String,
+    from: usize,
+    to: usize,
+    jump: isize,
+    accumulator_before: i64,
+    accumulator_after: i64,
+}
+
+#[derive(Serialize)]
+struct TraceErrorRecord {
+    step: usize,
+    at: usize,
+    accumulator: i64,
+    error: String,
+}
+
+#[derive(Default)]
+pub struct TraceCollector {
+    steps: Vec<Step>,
+    error: Option<(ProgramState, ExecutionError)>,
+}
+
+impl TraceCollector {
+    pub fn new() -> Self {
+        TraceCollector {
+            steps: Vec::new(),
+            error: None,
+        }
+    }
+
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    pub fn error(&self) -> Option<&(ProgramState, ExecutionError)> {
+        self.error.as_ref()
+    }
+
+    pub fn write_text<W: Write>(&self, mut out: W) -> io::Result<()> {
+        for (i, s) in self.steps.iter().enumerate() {
+            writeln!(
+                out,
+                "{:>6}  {:>5}: {:<12} -> {:>5} ({:+})  acc {} -> {}",
+                i,
+                s.before.instruction,
+                s.instruction.to_string(),
+                s.after.instruction,
+                s.jump(),
+                s.before.accumulator(),
+                s.after.accumulator()
+            )?;
+        }
+        if let Some((state, error)) = &self.error {
+            writeln!(
+                out,
+                "{:>6}  {:>5}: error: {}  acc {}",
+                self.steps.len(),
+                state.instruction,
+                error,
+                state.accumulator()
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn write_json_lines<W: Write>(&self, mut out: W) -> io::Result<()> {
+        for (i, s) in self.steps.iter().enumerate() {
+            let record = TraceRecord {
+                step: i,
+                instruction: s.instruction.to_string(),
+                from: s.before.instruction,
+                to: s.after.instruction,
+                jump: s.jump(),
+                accumulator_before: s.before.accumulator(),
+                accumulator_after: s.after.accumulator(),
+            };
+            serde_json::to_writer(&mut out, &record)?;
+            writeln!(out)?;
+        }
+        if let Some((state, error)) = &self.error {
+            let record = TraceErrorRecord {
+                step: self.steps.len(),
+                at: state.instruction,
+                accumulator: state.accumulator(),
+                error: error.to_string(),
+            };
+            serde_json::to_writer(&mut out, &record)?;
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
+
+impl Observer for TraceCollector {
+    fn after_step(&mut self, step: &Step) {
+        self.steps.push(step.clone());
+    }
+
+    fn on_error(&mut self, state: &ProgramState, error: &ExecutionError) {
+        self.error = Some((state.clone(), error.clone()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{Program, VirtualMachine};
+
+    fn collect(lines: &[&str]) -> TraceCollector {
+        let p = Program::parse_lines(lines).unwrap();
+        let mut trace = TraceCollector::new();
+        {
+            let mut vm = VirtualMachine::new(&p);
+            vm.observe(&mut trace);
+            let _ = vm.execute();
+        }
+        trace
+    }
+
+    #[test]
+    fn collects_steps() {
+        let trace = collect(&["nop +0", "acc +2", "jmp +2", "acc +5", "acc -1"]);
+        let jumps: Vec<_> = trace.steps().iter().map(Step::jump).collect();
+        assert_eq!(vec![1, 1, 2, 1], jumps);
+        assert_eq!(1, trace.steps().last().unwrap().after.accumulator());
+        assert!(trace.error().is_none());
+    }
+
+    #[test]
+    fn collects_errors() {
+        let trace = collect(&["nop +0", "acc +1", "jmp -1"]);
+        assert_eq!(2, trace.steps().len());
+        assert!(matches!(
+            trace.error(),
+            Some((
+                ProgramState { instruction: 2, .. },
+                ExecutionError::InfiniteLoop(1)
+            ))
+        ));
+    }
+
+    #[test]
+    fn writes_text() {
+        let trace = collect(&["nop +0", "acc +3", "jmp -1"]);
+        let mut out = Vec::new();
+        trace.write_text(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(3, lines.len());
+        assert_eq!(
+            "     1      1: acc +3       ->     2 (+1)  acc 0 -> 3",
+            lines[1]
+        );
+        assert_eq!(
+            "     2      2: error: Infinite loop detected at instruction 1  acc 3",
+            lines[2]
+        );
+    }
+
+    #[test]
+    fn writes_json_lines() {
+        let trace = collect(&["acc +3", "nop +0"]);
+        let mut out = Vec::new();
+        trace.write_json_lines(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(
+            concat!(
+                r#"{"step":0,"instruction":"acc +3","from":0,"to":1,"jump":1,"accumulator_before":0,"accumulator_after":3}"#,
+                "\n",
+                r#"{"step":1,"instruction":"nop +0","from":1,"to":2,"jump":1,"accumulator_before":3,"accumulator_after":3}"#,
+                "\n"
+            ),
+            out
+        );
+    }
+}
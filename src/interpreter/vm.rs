@@ -1,50 +1,304 @@
-use std::collections::HashSet;
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet, VecDeque},
+    sync::Arc,
+    time::Instant,
+};
 
-use super::{ExecutionError, ExecutionResult, Instruction, Program};
+use num_bigint::BigInt;
 
-#[derive(Debug, PartialEq, Copy, Clone)]
+use super::{
+    Arithmetic, Channel, ExecutionError, ExecutionLimits, ExecutionResult, Flow, Instruction,
+    LoopDetection, Mailbox, Memory, Observer, Operation, Program, Registers, Step,
+};
+
+/// Registers and memory are empty unless the state comes from a `MachineProfile`.
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone)]
 pub struct ProgramState {
     pub instruction: usize,
-    pub accumulator: i64,
+    /// Only written through the methods in `arithmetic`, which keep `wide` in step with it.
+    pub(super) accumulator: i64,
+    pub registers: Registers,
+    pub memory: Memory,
+    pub arithmetic: Arithmetic,
+    /// The exact accumulator when it does not fit in `accumulator`.
+    pub(super) wide: Option<Arc<BigInt>>,
 }
 
 impl ProgramState {
-    fn modify(&self, p: &Program, offset: isize, acc: i64) -> ExecutionResult<ProgramState> {
-        let new_instr = self.instruction as isize + offset;
-        if new_instr < 0 || new_instr as usize > p.len() {
-            Err(ExecutionError::InvalidAccess(new_instr as usize))
-        } else {
-            Ok(ProgramState {
-                instruction: new_instr as usize,
-                accumulator: self.accumulator + acc,
-            })
+    /// Moves by `offset` and adds `acc` in place, as the builtin instructions do. On failure the
+    /// state is left as it was.
+    pub(super) fn advance(&mut self, len: usize, offset: isize, acc: i64) -> ExecutionResult<()> {
+        let new_instr = (self.instruction as isize).saturating_add(offset);
+        if new_instr < 0 || new_instr as usize > len {
+            return Err(ExecutionError::InvalidAccess(new_instr));
+        }
+        if acc != 0 {
+            self.add_to_accumulator(acc)?;
+        }
+        self.instruction = new_instr as usize;
+        Ok(())
+    }
+
+    /// The fields `advance` changes, for undoing it with `rewind`.
+    pub(super) fn position(&self) -> Position {
+        Position {
+            instruction: self.instruction,
+            accumulator: self.accumulator,
+            wide: self.wide.clone(),
+        }
+    }
+
+    pub(super) fn rewind(&mut self, position: Position) {
+        self.instruction = position.instruction;
+        self.accumulator = position.accumulator;
+        self.wide = position.wide;
+    }
+
+    /// Runs a custom operation in a program of `len` instructions.
+    pub(super) fn apply(
+        &self,
+        op: &dyn Operation,
+        arg: i64,
+        len: usize,
+        channel: &mut dyn Channel,
+    ) -> ExecutionResult<ProgramState> {
+        let mut state = self.clone();
+        let flow = op.execute_with(arg, &mut state, channel)?;
+        state.instruction = self.instruction;
+        match flow {
+            Flow::Next => state.advance(len, 1, 0)?,
+            Flow::Jump(offset) => state.advance(len, offset, 0)?,
+            Flow::Halt => state.instruction = len,
         }
+        Ok(state)
+    }
+}
+
+/// See `ProgramState::position`.
+pub(super) struct Position {
+    instruction: usize,
+    accumulator: i64,
+    wide: Option<Arc<BigInt>>,
+}
+
+/// A past state together with whether stepping away from it added a new entry to the loop
+/// detection sets, so that stepping back can undo exactly that. Steps that rewrote the program
+/// also keep the program as it was before.
+#[derive(Debug, Clone)]
+struct HistoryEntry {
+    state: ProgramState,
+    recorded: bool,
+    rewritten: Option<(Program, u32)>,
+}
+
+/// Everything needed to put a `VirtualMachine` back where it was, see `snapshot`. Snapshots are
+/// only meaningful for the program they were taken from.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    state: ProgramState,
+    program: Option<(Program, u32)>,
+    visited: HashSet<(usize, u32)>,
+    seen_states: HashSet<(ProgramState, u32)>,
+    steps: u64,
+    history: VecDeque<HistoryEntry>,
+}
+
+impl Snapshot {
+    pub fn state(&self) -> &ProgramState {
+        &self.state
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
     }
 }
 
 pub struct VirtualMachine<'a> {
-    program: &'a Program,
+    program: Cow<'a, Program>,
+    /// Which version of the program contents is running, which loop detection pairs with every
+    /// state. It only changes on machines that own their program.
+    revision: u32,
+    /// Every version an owned program has gone through, numbered in order of appearance.
+    versions: HashMap<Vec<Instruction>, u32>,
     current_state: ProgramState,
-    visited: HashSet<usize>,
+    limits: ExecutionLimits,
+    visited: HashSet<(usize, u32)>,
+    seen_states: HashSet<(ProgramState, u32)>,
+    steps: u64,
+    started: Option<Instant>,
+    history: VecDeque<HistoryEntry>,
+    history_capacity: usize,
+    mailbox: Mailbox,
+    observers: Vec<&'a mut dyn Observer>,
 }
 
 impl<'a> VirtualMachine<'a> {
     pub fn new(program: &'a Program) -> Self {
-        VirtualMachine {
+        Self::with_limits(program, ExecutionLimits::new())
+    }
+
+    pub fn with_limits(program: &'a Program, limits: ExecutionLimits) -> Self {
+        Self::with_state(program, limits, ProgramState::default())
+    }
+
+    /// Starts from `state` instead of the default, for example `MachineProfile::initial_state`.
+    pub fn with_state(program: &'a Program, limits: ExecutionLimits, state: ProgramState) -> Self {
+        Self::build(Cow::Borrowed(program), limits, state)
+    }
+
+    /// Runs a copy of `program` that operations such as `tgl` may rewrite. A state only counts
+    /// as seen before if the program also looked the same.
+    pub fn self_modifying(program: Program, limits: ExecutionLimits) -> Self {
+        Self::build(Cow::Owned(program), limits, ProgramState::default())
+    }
+
+    fn build(program: Cow<'a, Program>, limits: ExecutionLimits, state: ProgramState) -> Self {
+        let mut versions = HashMap::new();
+        if let Cow::Owned(p) = &program {
+            versions.insert(p.instructions().to_vec(), 0);
+        }
+        let mut vm = VirtualMachine {
             program,
-            current_state: ProgramState {
-                instruction: 0,
-                accumulator: 0,
-            },
+            revision: 0,
+            versions,
+            current_state: state,
+            limits,
             visited: HashSet::new(),
+            seen_states: HashSet::new(),
+            steps: 0,
+            started: None,
+            history: VecDeque::new(),
+            history_capacity: 0,
+            mailbox: Mailbox::default(),
+            observers: Vec::new(),
+        };
+        vm.record(0);
+        vm
+    }
+
+    /// The program as it is now, which differs from the original if it rewrote itself.
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    pub fn limits(&self) -> &ExecutionLimits {
+        &self.limits
+    }
+
+    /// Number of instructions executed so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Keeps the last `capacity` states so that execution can be stepped back. History is off by
+    /// default.
+    pub fn keep_history(&mut self, capacity: usize) {
+        self.history_capacity = capacity;
+        while self.history.len() > capacity {
+            self.history.pop_front();
         }
     }
 
+    /// Past states, oldest first; the last one is the state before the current one.
+    pub fn history(&self) -> impl DoubleEndedIterator<Item = &ProgramState> {
+        self.history.iter().map(|e| &e.state)
+    }
+
+    /// Undoes the last step. Returns false if there is no history left. Observers are not
+    /// notified and values sent or received stay where they are.
+    pub fn step_back(&mut self) -> bool {
+        let entry = match self.history.pop_back() {
+            Some(e) => e,
+            None => return false,
+        };
+        if entry.recorded {
+            match self.limits.loop_detection {
+                LoopDetection::Disabled => {}
+                LoopDetection::Address => {
+                    self.visited
+                        .remove(&(self.current_state.instruction, self.revision));
+                }
+                LoopDetection::State => {
+                    let key = (self.current_state.clone(), self.revision);
+                    self.seen_states.remove(&key);
+                }
+            }
+        }
+        if let Some((program, revision)) = entry.rewritten {
+            *self.program.to_mut() = program;
+            self.revision = revision;
+        }
+        self.current_state = entry.state;
+        self.steps -= 1;
+        true
+    }
+
+    /// Steps back until `steps()` equals `step`. Fails without changing anything if `step` is in
+    /// the future or older than the kept history.
+    pub fn rewind_to(&mut self, step: u64) -> bool {
+        if step > self.steps || self.steps - step > self.history.len() as u64 {
+            return false;
+        }
+        while self.steps > step {
+            self.step_back();
+        }
+        true
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            state: self.current_state.clone(),
+            program: match &self.program {
+                Cow::Borrowed(_) => None,
+                Cow::Owned(p) => Some((p.clone(), self.revision)),
+            },
+            visited: self.visited.clone(),
+            seen_states: self.seen_states.clone(),
+            steps: self.steps,
+            history: self.history.clone(),
+        }
+    }
+
+    /// Values sent or received since the snapshot stay where they are, as with `step_back`.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.current_state = snapshot.state.clone();
+        if let Some((program, revision)) = &snapshot.program {
+            *self.program.to_mut() = program.clone();
+            self.revision = *revision;
+        }
+        self.visited = snapshot.visited.clone();
+        self.seen_states = snapshot.seen_states.clone();
+        self.steps = snapshot.steps;
+        self.history = snapshot.history.clone();
+        self.keep_history(self.history_capacity);
+    }
+
+    /// Queues a value for `rcv`.
+    pub fn send(&mut self, value: i64) {
+        self.mailbox.inbox.push_back(value);
+    }
+
+    /// Takes the oldest value sent with `snd`.
+    pub fn receive(&mut self) -> Option<i64> {
+        self.mailbox.outbox.pop_front()
+    }
+
+    pub fn mailbox(&self) -> &Mailbox {
+        &self.mailbox
+    }
+
+    pub fn observe(&mut self, observer: &'a mut dyn Observer) {
+        self.observers.push(observer);
+    }
+
+    /// Runs to the end and returns the final accumulator. Under `Arithmetic::Unbounded` that is
+    /// only its lowest 64 bits, see `ProgramState::exact_accumulator` for the whole value.
     pub fn execute(&mut self) -> ExecutionResult<i64> {
         while !self.terminated() {
             self.execute_one()?;
         }
-        Ok(self.current_state.accumulator)
+        Ok(self.current_state.accumulator())
     }
 
     pub fn execute_one(&mut self) -> ExecutionResult<()> {
@@ -52,22 +306,147 @@ impl<'a> VirtualMachine<'a> {
             return Ok(());
         }
 
-        let instr = self
+        let result = self.step();
+        match &result {
+            // Waiting for a value is not a failure; the instruction will be retried.
+            Err(ExecutionError::Blocked(_)) | Ok(_) => {}
+            Err(e) => {
+                for o in self.observers.iter_mut() {
+                    o.on_error(&self.current_state, e);
+                }
+            }
+        }
+        result
+    }
+
+    fn step(&mut self) -> ExecutionResult<()> {
+        self.check_limits()?;
+        let instr = *self
             .program
             .get_instr(self.current_state.instruction)
             .ok_or(ExecutionError::InvalidAccess(
-                self.current_state.instruction,
+                self.current_state.instruction as isize,
             ))?;
-        let next_state = match instr {
-            Instruction::Nop(_) => self.modify_state(1, 0)?,
-            Instruction::Jmp(i) => self.modify_state(*i, 0)?,
-            Instruction::Acc(i) => self.modify_state(1, *i)?,
-        };
-        if self.visited.insert(next_state.instruction) {
-            self.current_state = next_state;
-            Ok(())
+        for o in self.observers.iter_mut() {
+            o.before_step(&self.current_state, &instr);
+        }
+        // Copies of the state are only needed by history and observers; builtins otherwise
+        // update it in place.
+        let before = if self.history_capacity > 0 || !self.observers.is_empty() {
+            Some(self.current_state.clone())
         } else {
-            Err(ExecutionError::InfiniteLoop(next_state.instruction))
+            None
+        };
+        let position = self.current_state.position();
+        let len = self.program.len();
+        let mut replaced = None;
+        let mut rewritten = None;
+        let mut traffic = None;
+        match instr {
+            Instruction::Nop(_) => self.current_state.advance(len, 1, 0)?,
+            Instruction::Jmp(i) => self.current_state.advance(len, i, 0)?,
+            Instruction::Acc(i) => self.current_state.advance(len, 1, i)?,
+            Instruction::Custom(op, arg) => {
+                if op.modifies_program() {
+                    let program = match &self.program {
+                        Cow::Owned(p) => p,
+                        Cow::Borrowed(_) => {
+                            let at = self.current_state.instruction;
+                            return Err(ExecutionError::ReadOnlyProgram(at));
+                        }
+                    };
+                    if let Some(program) = op.modify_program(arg, &self.current_state, program)? {
+                        let revision = self.intern(&program);
+                        rewritten = Some((program, revision));
+                    }
+                }
+                let mut channel = self.mailbox.tentative();
+                let state = self.current_state.apply(op, arg, len, &mut channel)?;
+                traffic = Some(channel.into_traffic());
+                replaced = Some(std::mem::replace(&mut self.current_state, state));
+            }
+        }
+        let revision = rewritten.as_ref().map_or(self.revision, |(_, r)| *r);
+        if !self.record(revision) {
+            let state = &self.current_state;
+            let error = match self.limits.loop_detection {
+                LoopDetection::State => {
+                    ExecutionError::RepeatedState(state.instruction, state.accumulator())
+                }
+                _ => ExecutionError::InfiniteLoop(state.instruction),
+            };
+            match replaced {
+                Some(state) => self.current_state = state,
+                None => self.current_state.rewind(position),
+            }
+            return Err(error);
+        }
+        if let Some(traffic) = traffic {
+            self.mailbox.commit(traffic);
+        }
+        let rewritten = rewritten.map(|(program, revision)| {
+            let program = std::mem::replace(self.program.to_mut(), program);
+            (program, std::mem::replace(&mut self.revision, revision))
+        });
+        self.steps += 1;
+        if let Some(mut before) = before {
+            if !self.observers.is_empty() {
+                let step = Step {
+                    instruction: instr,
+                    before,
+                    after: self.current_state.clone(),
+                };
+                for o in self.observers.iter_mut() {
+                    o.after_step(&step);
+                }
+                before = step.before;
+            }
+            if self.history_capacity > 0 {
+                if self.history.len() == self.history_capacity {
+                    self.history.pop_front();
+                }
+                self.history.push_back(HistoryEntry {
+                    state: before,
+                    recorded: self.limits.loop_detection != LoopDetection::Disabled,
+                    rewritten,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn check_limits(&mut self) -> ExecutionResult<()> {
+        if let Some(max) = self.limits.max_steps {
+            if self.steps >= max {
+                return Err(ExecutionError::StepLimitExceeded(max));
+            }
+        }
+        if let Some(budget) = self.limits.time_budget {
+            if self.started.get_or_insert_with(Instant::now).elapsed() > budget {
+                return Err(ExecutionError::TimeLimitExceeded(budget));
+            }
+        }
+        Ok(())
+    }
+
+    fn intern(&mut self, program: &Program) -> u32 {
+        if let Some(revision) = self.versions.get(program.instructions()) {
+            return *revision;
+        }
+        let revision = self.versions.len() as u32;
+        self.versions
+            .insert(program.instructions().to_vec(), revision);
+        revision
+    }
+
+    /// Remembers the current state for loop detection, returning false if it has been seen
+    /// before.
+    fn record(&mut self, revision: u32) -> bool {
+        let state = &self.current_state;
+        match self.limits.loop_detection {
+            LoopDetection::Disabled => true,
+            LoopDetection::Address => self.visited.insert((state.instruction, revision)),
+            LoopDetection::State => self.seen_states.insert((state.clone(), revision)),
         }
     }
 
@@ -78,15 +457,54 @@ impl<'a> VirtualMachine<'a> {
     pub fn current_state(&self) -> &ProgramState {
         &self.current_state
     }
+}
+
+/// The execution API shared by the interpreting `VirtualMachine` and the `CompiledMachine`.
+pub trait Machine {
+    fn execute_one(&mut self) -> ExecutionResult<()>;
+
+    fn terminated(&self) -> bool;
+
+    fn current_state(&self) -> &ProgramState;
+
+    fn steps(&self) -> u64;
+
+    /// Like `VirtualMachine::execute`, truncated under `Arithmetic::Unbounded` as well.
+    fn execute(&mut self) -> ExecutionResult<i64> {
+        while !self.terminated() {
+            self.execute_one()?;
+        }
+        Ok(self.current_state().accumulator())
+    }
+}
+
+impl Machine for VirtualMachine<'_> {
+    fn execute_one(&mut self) -> ExecutionResult<()> {
+        VirtualMachine::execute_one(self)
+    }
 
-    fn modify_state(&self, offset: isize, acc: i64) -> ExecutionResult<ProgramState> {
-        self.current_state.modify(&self.program, offset, acc)
+    fn terminated(&self) -> bool {
+        VirtualMachine::terminated(self)
+    }
+
+    fn current_state(&self) -> &ProgramState {
+        VirtualMachine::current_state(self)
+    }
+
+    fn steps(&self) -> u64 {
+        VirtualMachine::steps(self)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::interpreter::InstructionSet;
+    use std::time::Duration;
+
+    const EXAMPLE: [&str; 9] = [
+        "nop +0", "acc +1", "jmp +4", "acc +3", "jmp -3", "acc -99", "acc +1", "jmp -4", "acc +6",
+    ];
 
     #[test]
     pub fn runs_simple_program() {
@@ -94,4 +512,204 @@ mod tests {
         let mut vm = VirtualMachine::new(&p);
         assert_eq!(Ok(4), vm.execute());
     }
+
+    #[test]
+    pub fn runs_custom_instructions() {
+        let set = InstructionSet::extended();
+        let p = Program::parse_lines_with(
+            &["acc 3", "mul 5", "jnz 2", "acc 100", "hlt", "acc 100"],
+            &set,
+        )
+        .unwrap();
+        let mut vm = VirtualMachine::new(&p);
+        assert_eq!(Ok(15), vm.execute());
+
+        let p = Program::parse_lines_with(&["acc 2", "jmpa", "acc 100", "jz -1"], &set).unwrap();
+        let mut vm = VirtualMachine::new(&p);
+        assert_eq!(Ok(2), vm.execute());
+    }
+
+    #[derive(Default)]
+    struct Counter {
+        before: usize,
+        after: usize,
+        errors: usize,
+    }
+
+    impl Observer for Counter {
+        fn before_step(&mut self, _: &ProgramState, _: &Instruction) {
+            self.before += 1;
+        }
+
+        fn after_step(&mut self, _: &Step) {
+            self.after += 1;
+        }
+
+        fn on_error(&mut self, _: &ProgramState, _: &ExecutionError) {
+            self.errors += 1;
+        }
+    }
+
+    #[test]
+    pub fn notifies_observers() {
+        let p = Program::parse_lines(&["nop +0", "acc +1", "jmp -2"]).unwrap();
+        let mut first = Counter::default();
+        let mut second = Counter::default();
+        {
+            let mut vm = VirtualMachine::new(&p);
+            vm.observe(&mut first);
+            vm.observe(&mut second);
+            assert_eq!(Err(ExecutionError::InfiniteLoop(0)), vm.execute());
+        }
+        for c in &[first, second] {
+            assert_eq!(3, c.before);
+            assert_eq!(2, c.after);
+            assert_eq!(1, c.errors);
+        }
+    }
+
+    #[test]
+    pub fn detects_loop_back_to_start() {
+        let p = Program::parse_lines(&["acc +1", "jmp -1"]).unwrap();
+        let mut vm = VirtualMachine::new(&p);
+        assert_eq!(Err(ExecutionError::InfiniteLoop(0)), vm.execute());
+        assert_eq!(1, vm.current_state().accumulator());
+        assert_eq!(1, vm.steps());
+    }
+
+    #[test]
+    pub fn detects_repeated_state() {
+        let set = InstructionSet::extended();
+        let p = Program::parse_lines_with(&["acc +3", "acc -1", "jnz -1", "jmp -1"], &set).unwrap();
+        let limits = ExecutionLimits::new().loop_detection(LoopDetection::State);
+        let mut vm = VirtualMachine::with_limits(&p, limits);
+        assert_eq!(Err(ExecutionError::RepeatedState(2, 0)), vm.execute());
+        assert_eq!(7, vm.steps());
+
+        let mut vm = VirtualMachine::new(&p);
+        assert_eq!(Err(ExecutionError::InfiniteLoop(1)), vm.execute());
+    }
+
+    #[test]
+    pub fn rejected_steps_keep_channel_values() {
+        let set = InstructionSet::extended();
+        let p = Program::parse_lines_with(&["jmp +2", "rcv", "jmp -1"], &set).unwrap();
+        let mut vm = VirtualMachine::new(&p);
+        vm.send(5);
+        vm.send(6);
+        assert_eq!(Err(ExecutionError::InfiniteLoop(2)), vm.execute());
+        assert_eq!(vec![5, 6], Vec::from(vm.mailbox().inbox.clone()));
+        assert_eq!(0, vm.current_state().accumulator());
+
+        let p = Program::parse_lines_with(&["acc +1", "jmp +2", "snd", "jmp -1"], &set).unwrap();
+        let mut vm = VirtualMachine::new(&p);
+        assert_eq!(Err(ExecutionError::InfiniteLoop(3)), vm.execute());
+        assert_eq!(None, vm.receive());
+    }
+
+    #[test]
+    pub fn interns_program_versions() {
+        let set = InstructionSet::extended();
+        let p = Program::parse_lines_with(&["tgl +1", "acc +1", "tgl -5"], &set).unwrap();
+        let mut vm = VirtualMachine::self_modifying(p, ExecutionLimits::new());
+        assert_eq!(Ok(1), vm.execute());
+        assert_eq!(1, vm.versions.len());
+
+        let p = Program::parse_lines_with(&["tgl +2", "tgl +1", "nop +0"], &set).unwrap();
+        let mut vm = VirtualMachine::self_modifying(p, ExecutionLimits::new());
+        assert_eq!(Ok(0), vm.execute());
+        assert_eq!(2, vm.versions.len());
+        assert_eq!(0, vm.revision);
+    }
+
+    #[test]
+    pub fn enforces_step_limit() {
+        let p = Program::parse_lines(&["acc +1", "jmp -1"]).unwrap();
+        let mut vm = VirtualMachine::with_limits(&p, ExecutionLimits::unlimited().max_steps(7));
+        assert_eq!(Err(ExecutionError::StepLimitExceeded(7)), vm.execute());
+        assert_eq!(4, vm.current_state().accumulator());
+
+        let p = Program::parse_lines(&["acc +1", "acc +1"]).unwrap();
+        let mut vm = VirtualMachine::with_limits(&p, ExecutionLimits::new().max_steps(2));
+        assert_eq!(Ok(2), vm.execute());
+    }
+
+    #[test]
+    pub fn enforces_time_budget() {
+        let p = Program::parse_lines(&["jmp +0"]).unwrap();
+        let budget = Duration::from_millis(20);
+        let limits = ExecutionLimits::unlimited().time_budget(budget);
+        let mut vm = VirtualMachine::with_limits(&p, limits);
+        assert_eq!(Err(ExecutionError::TimeLimitExceeded(budget)), vm.execute());
+        assert!(vm.steps() > 0);
+    }
+
+    #[test]
+    pub fn steps_back() {
+        let p = Program::parse_lines(&EXAMPLE).unwrap();
+        let mut vm = VirtualMachine::new(&p);
+        vm.keep_history(10);
+        assert_eq!(Err(ExecutionError::InfiniteLoop(1)), vm.execute());
+        assert_eq!(6, vm.steps());
+
+        // The loop closes at 1, so walk back to the step that first entered it.
+        while vm.current_state().instruction != 1 {
+            assert!(vm.step_back());
+        }
+        assert_eq!(1, vm.steps());
+        assert_eq!(0, vm.history().last().unwrap().instruction);
+
+        // Stepping back also forgets the visits, so the run repeats exactly.
+        assert_eq!(Err(ExecutionError::InfiniteLoop(1)), vm.execute());
+        assert_eq!(5, vm.current_state().accumulator());
+
+        vm.keep_history(2);
+        assert_eq!(
+            vec![7, 3],
+            vm.history().map(|s| s.instruction).collect::<Vec<_>>()
+        );
+        assert!(!vm.rewind_to(3));
+        assert!(vm.rewind_to(4));
+        assert_eq!(7, vm.current_state().instruction);
+        assert!(!vm.step_back());
+    }
+
+    #[test]
+    pub fn snapshots_and_rewinds() {
+        let p = Program::parse_lines(&EXAMPLE).unwrap();
+        let limits = ExecutionLimits::new().loop_detection(LoopDetection::State);
+        let mut vm = VirtualMachine::with_limits(&p, limits);
+        vm.keep_history(100);
+        for _ in 0..3 {
+            vm.execute_one().unwrap();
+        }
+        let snapshot = vm.snapshot();
+        for _ in 0..10 {
+            vm.execute_one().unwrap();
+        }
+        assert!(!vm.rewind_to(14));
+        assert!(vm.rewind_to(5));
+        assert_eq!(5, vm.steps());
+        assert_eq!(
+            ProgramState {
+                instruction: 3,
+                accumulator: 2,
+                ..ProgramState::default()
+            },
+            *vm.current_state()
+        );
+
+        vm.restore(&snapshot);
+        assert_eq!(3, vm.steps());
+        assert_eq!(snapshot.state(), vm.current_state());
+        assert!(vm.rewind_to(0));
+        assert_eq!(
+            ProgramState {
+                instruction: 0,
+                accumulator: 0,
+                ..ProgramState::default()
+            },
+            *vm.current_state()
+        );
+    }
 }
@@ -0,0 +1,129 @@
+use std::{iter::FusedIterator, ops::Range, sync::Arc};
+
+use super::Pairwise;
+
+/// Items collected once and shared, so that iterating them again only clones a pointer. Each
+/// pass yields clones of the items.
+#[derive(Debug)]
+pub struct Buffered<T> {
+    items: Arc<[T]>,
+}
+
+impl<T> Clone for Buffered<T> {
+    fn clone(&self) -> Self {
+        Buffered {
+            items: Arc::clone(&self.items),
+        }
+    }
+}
+
+impl<T> From<Vec<T>> for Buffered<T> {
+    fn from(items: Vec<T>) -> Self {
+        Buffered {
+            items: items.into(),
+        }
+    }
+}
+
+impl<T> std::iter::FromIterator<T> for Buffered<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        iter.into_iter().collect::<Vec<_>>().into()
+    }
+}
+
+impl<T: Clone> IntoIterator for Buffered<T> {
+    type Item = T;
+    type IntoIter = BufferedIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        BufferedIter {
+            range: 0..self.items.len(),
+            items: self.items,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct BufferedIter<T> {
+    items: Arc<[T]>,
+    range: Range<usize>,
+}
+
+impl<T> Clone for BufferedIter<T> {
+    fn clone(&self) -> Self {
+        BufferedIter {
+            items: Arc::clone(&self.items),
+            range: self.range.clone(),
+        }
+    }
+}
+
+impl<T: Clone> Iterator for BufferedIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.range.next().map(|i| self.items[i].clone())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.range.size_hint()
+    }
+
+    fn nth(&mut self, n: usize) -> Option<T> {
+        self.range.nth(n).map(|i| self.items[i].clone())
+    }
+}
+
+impl<T: Clone> DoubleEndedIterator for BufferedIter<T> {
+    fn next_back(&mut self) -> Option<T> {
+        self.range.next_back().map(|i| self.items[i].clone())
+    }
+}
+
+impl<T: Clone> ExactSizeIterator for BufferedIter<T> {}
+
+impl<T: Clone> FusedIterator for BufferedIter<T> {}
+
+impl<I1, T> Pairwise<I1, Buffered<T>>
+where
+    I1: IntoIterator<Item: Clone>,
+    T: Clone,
+{
+    /// Pairs with a second iterator that can only be consumed once, by collecting it first.
+    pub fn buffered<I2>(a: I1, b: I2) -> Self
+    where
+        I2: IntoIterator<Item = T>,
+    {
+        Pairwise::from(a, b.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_shot_iterators() {
+        let mut countdown = 3;
+        let once = std::iter::from_fn(move || {
+            countdown -= 1;
+            Some(countdown).filter(|&c| c >= 0)
+        });
+        let p: Vec<_> = Pairwise::buffered(vec!['a', 'b'], once).collect();
+        assert_eq!(
+            vec![('a', 2), ('a', 1), ('a', 0), ('b', 2), ('b', 1), ('b', 0)],
+            p
+        );
+    }
+
+    #[test]
+    fn owned_items() {
+        let names = vec!["x".to_string(), "y".to_string()];
+        let suffixes = vec!["1".to_string(), "2".to_string()];
+        let mut p = Pairwise::buffered(names, suffixes);
+        assert_eq!(Some(4), p.checked_len());
+        assert_eq!(Some(("y".to_string(), "2".to_string())), p.next_back());
+        let joined: Vec<String> = p.map(|(a, b)| a + &b).collect();
+        assert_eq!(vec!["x1", "x2", "y1"], joined);
+    }
+}
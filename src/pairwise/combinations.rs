@@ -0,0 +1,237 @@
+use std::{convert::TryFrom, iter::FusedIterator};
+
+/// Selections of `k` elements at distinct positions of a slice, each yielded as a `Vec` of
+/// references. Both iterators follow the lexicographic order of the selected indices, so a
+/// sorted slice gives sorted output.
+pub trait Selections<T> {
+    /// Every subset of `k` positions, in increasing index order within each selection.
+    fn combinations(&self, k: usize) -> Combinations<'_, T>;
+
+    /// Every arrangement of `k` distinct positions.
+    fn permutations(&self, k: usize) -> Permutations<'_, T>;
+}
+
+impl<T> Selections<T> for [T] {
+    fn combinations(&self, k: usize) -> Combinations<'_, T> {
+        Combinations {
+            items: self,
+            indices: (0..k).collect(),
+            remaining: binomial(self.len(), k),
+        }
+    }
+
+    fn permutations(&self, k: usize) -> Permutations<'_, T> {
+        let mut used = vec![false; self.len()];
+        used.iter_mut().take(k).for_each(|u| *u = true);
+        Permutations {
+            items: self,
+            indices: (0..k).collect(),
+            used,
+            remaining: falling_factorial(self.len(), k),
+        }
+    }
+}
+
+/// `None` when the count does not fit in a `usize`.
+fn binomial(n: usize, k: usize) -> Option<usize> {
+    if k > n {
+        return Some(0);
+    }
+    // Every step gives a binomial no larger than the result, so with the result in a `usize`
+    // the product before the division fits in a `u128`.
+    let k = k.min(n - k);
+    let c = (0..k).try_fold(1u128, |c, i| {
+        Some(c.checked_mul((n - i) as u128)? / (i as u128 + 1))
+    })?;
+    usize::try_from(c).ok()
+}
+
+fn falling_factorial(n: usize, k: usize) -> Option<usize> {
+    if k > n {
+        return Some(0);
+    }
+    (0..k).try_fold(1usize, |p, i| p.checked_mul(n - i))
+}
+
+fn remaining_hint(remaining: Option<usize>) -> (usize, Option<usize>) {
+    match remaining {
+        Some(n) => (n, Some(n)),
+        None => (0, None),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Combinations<'a, T> {
+    items: &'a [T],
+    indices: Vec<usize>,
+    remaining: Option<usize>,
+}
+
+impl<'a, T> Iterator for Combinations<'a, T> {
+    type Item = Vec<&'a T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == Some(0) {
+            return None;
+        }
+        let item = self.indices.iter().map(|&i| &self.items[i]).collect();
+        self.remaining = self.remaining.map(|n| n - 1);
+
+        // The rightmost index that can still move right, then everything after it packed
+        // behind it.
+        let (n, k) = (self.items.len(), self.indices.len());
+        match (0..k).rev().find(|&i| self.indices[i] < n - k + i) {
+            Some(i) => {
+                self.indices[i] += 1;
+                for j in i + 1..k {
+                    self.indices[j] = self.indices[j - 1] + 1;
+                }
+            }
+            None => self.remaining = Some(0),
+        }
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        remaining_hint(self.remaining)
+    }
+}
+
+impl<T> FusedIterator for Combinations<'_, T> {}
+
+#[derive(Debug, Clone)]
+pub struct Permutations<'a, T> {
+    items: &'a [T],
+    indices: Vec<usize>,
+    used: Vec<bool>,
+    remaining: Option<usize>,
+}
+
+impl<'a, T> Iterator for Permutations<'a, T> {
+    type Item = Vec<&'a T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == Some(0) {
+            return None;
+        }
+        let item = self.indices.iter().map(|&i| &self.items[i]).collect();
+        self.remaining = self.remaining.map(|n| n - 1);
+
+        // Give the rightmost possible position the next larger unused index, then fill the
+        // positions after it with the smallest unused indices.
+        let n = self.items.len();
+        for i in (0..self.indices.len()).rev() {
+            self.used[self.indices[i]] = false;
+            if let Some(next) = (self.indices[i] + 1..n).find(|&j| !self.used[j]) {
+                self.indices[i] = next;
+                self.used[next] = true;
+                let mut free = 0;
+                for index in &mut self.indices[i + 1..] {
+                    while self.used[free] {
+                        free += 1;
+                    }
+                    *index = free;
+                    self.used[free] = true;
+                }
+                return Some(item);
+            }
+        }
+        self.remaining = Some(0);
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        remaining_hint(self.remaining)
+    }
+}
+
+impl<T> FusedIterator for Permutations<'_, T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn collect<'a>(it: impl Iterator<Item = Vec<&'a i32>>) -> Vec<Vec<i32>> {
+        it.map(|v| v.into_iter().copied().collect()).collect()
+    }
+
+    #[test]
+    fn combinations() {
+        let data = [1, 2, 3, 4];
+        assert_eq!(
+            vec![vec![1, 2, 3], vec![1, 2, 4], vec![1, 3, 4], vec![2, 3, 4]],
+            collect(data.combinations(3))
+        );
+        assert_eq!(vec![Vec::<i32>::new()], collect(data.combinations(0)));
+        assert_eq!(1, data.combinations(4).count());
+        assert_eq!(0, data.combinations(5).count());
+        assert_eq!(0, data[..0].combinations(1).count());
+    }
+
+    #[test]
+    fn permutations() {
+        let data = [1, 2, 3];
+        assert_eq!(
+            vec![
+                vec![1, 2],
+                vec![1, 3],
+                vec![2, 1],
+                vec![2, 3],
+                vec![3, 1],
+                vec![3, 2]
+            ],
+            collect(data.permutations(2))
+        );
+        let all = collect(data.permutations(3));
+        assert_eq!(vec![1, 2, 3], all[0]);
+        assert_eq!(vec![3, 2, 1], all[5]);
+        assert_eq!(6, all.len());
+        assert_eq!(vec![Vec::<i32>::new()], collect(data.permutations(0)));
+        assert_eq!(0, data.permutations(4).count());
+    }
+
+    #[test]
+    fn distinct_positions_not_values() {
+        let data = [7, 7];
+        assert_eq!(vec![vec![7, 7]], collect(data.combinations(2)));
+        assert_eq!(2, data.permutations(2).count());
+    }
+
+    #[test]
+    fn sizes() {
+        let data: Vec<i32> = (0..8).collect();
+        for k in 0..=9 {
+            let mut c = data.combinations(k);
+            let mut p = data.permutations(k);
+            let (c_len, p_len) = (c.size_hint().0, p.size_hint().0);
+            assert_eq!(c_len, c.clone().count());
+            assert_eq!(p_len, p.clone().count());
+            if c.next().is_some() {
+                assert_eq!((c_len - 1, Some(c_len - 1)), c.size_hint());
+            }
+            if p.next().is_some() {
+                assert_eq!((p_len - 1, Some(p_len - 1)), p.size_hint());
+            }
+        }
+        assert_eq!(56, data.combinations(5).count());
+        assert_eq!(6720, data.permutations(5).count());
+
+        let big = [0u8; 200];
+        assert_eq!((0, None), big.combinations(100).size_hint());
+        assert_eq!((0, None), big.permutations(100).size_hint());
+        let exact = 14_226_520_737_620_288_370;
+        assert_eq!((exact, Some(exact)), big[..67].combinations(33).size_hint());
+        assert_eq!((exact, Some(exact)), big[..67].combinations(34).size_hint());
+    }
+
+    #[test]
+    fn sorted_input_gives_sorted_output() {
+        let data = [1, 3, 5, 8, 9];
+        for k in 0..=5 {
+            let c = collect(data.combinations(k));
+            let p = collect(data.permutations(k));
+            assert!(c.windows(2).all(|w| w[0] < w[1]));
+            assert!(p.windows(2).all(|w| w[0] < w[1]));
+        }
+    }
+}
@@ -0,0 +1,401 @@
+use std::iter::FusedIterator;
+
+mod buffered;
+mod combinations;
+mod parallel;
+mod product;
+
+pub use buffered::*;
+pub use combinations::*;
+pub use parallel::*;
+pub use product::*;
+
+/// Every pair of an item of the first iterator with an item of the second, which is cloned
+/// afresh for each item of the first. A second iterator that cannot be cloned, should not be
+/// recomputed or owns its items, like that of a `Vec`, can be collected into a `Buffered` with
+/// `Pairwise::buffered` so that restarting it only clones a pointer. With both inputs
+/// `ExactSizeIterator`, `checked_len` is known and `nth` jumps straight to the row and column,
+/// which is O(1) for slices and ranges. There can be more pairs than fit in a `usize`, so
+/// `Pairwise` is not an `ExactSizeIterator` itself.
+pub struct Pairwise<I1, I2>
+where
+    I1: IntoIterator,
+    I2: IntoIterator,
+{
+    /// Never advanced, only cloned to start each row.
+    iter2: I2::IntoIter,
+
+    rows: I1::IntoIter,
+    front: Option<(I1::Item, I2::IntoIter)>,
+    back: Option<(I1::Item, I2::IntoIter)>,
+}
+
+fn exact((lower, upper): (usize, Option<usize>)) -> Option<usize> {
+    upper.filter(|&upper| upper == lower)
+}
+
+impl<I1, I2> Pairwise<I1, I2>
+where
+    I1: IntoIterator<Item: Clone>,
+    I2: IntoIterator<IntoIter: Clone>,
+{
+    pub fn from(a: I1, b: I2) -> Self {
+        Pairwise {
+            iter2: b.into_iter(),
+
+            rows: a.into_iter(),
+            front: None,
+            back: None,
+        }
+    }
+
+    /// The number of remaining pairs, or `None` if it is not known exactly or does not fit in a
+    /// `usize`.
+    pub fn checked_len(&self) -> Option<usize> {
+        exact(self.size_hint())
+    }
+
+    /// The `index`th of the remaining pairs, leaving the iterator where it is.
+    pub fn get(&self, index: usize) -> Option<(I1::Item, I2::Item)>
+    where
+        I1::IntoIter: ExactSizeIterator + Clone,
+        I2::IntoIter: ExactSizeIterator + Clone,
+    {
+        self.clone().nth(index)
+    }
+
+    /// Remaining pairs in the front row, the rows in between and the back row, if all of them
+    /// are known exactly.
+    fn exact_parts(&self) -> Option<(usize, usize, usize, usize)> {
+        let row = exact(self.iter2.size_hint())?;
+        let rows = exact(self.rows.size_hint())?;
+        let partial = |part: &Option<(I1::Item, I2::IntoIter)>| match part {
+            Some((_, ys)) => exact(ys.size_hint()),
+            None => Some(0),
+        };
+        Some((partial(&self.front)?, row, rows, partial(&self.back)?))
+    }
+}
+
+impl<I1, I2> Clone for Pairwise<I1, I2>
+where
+    I1: IntoIterator<IntoIter: Clone, Item: Clone>,
+    I2: IntoIterator<IntoIter: Clone>,
+{
+    fn clone(&self) -> Self {
+        Pairwise {
+            iter2: self.iter2.clone(),
+
+            rows: self.rows.clone(),
+            front: self.front.clone(),
+            back: self.back.clone(),
+        }
+    }
+}
+
+impl<I1, I2> Iterator for Pairwise<I1, I2>
+where
+    I1: IntoIterator<Item: Clone>,
+    I2: IntoIterator<IntoIter: Clone>,
+{
+    type Item = (I1::Item, I2::Item);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((x, ys)) = &mut self.front {
+                if let Some(y) = ys.next() {
+                    return Some((x.clone(), y));
+                }
+                self.front = None;
+            }
+            match self.rows.next() {
+                Some(x) => self.front = Some((x, self.iter2.clone())),
+                // Only the row started by `next_back` is left.
+                None => {
+                    let (x, ys) = self.back.as_mut()?;
+                    let item = ys.next().map(|y| (x.clone(), y));
+                    if item.is_none() {
+                        self.back = None;
+                    }
+                    return item;
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (row_lower, row_upper) = self.iter2.size_hint();
+        let (rows_lower, rows_upper) = self.rows.size_hint();
+        let partial = |part: &Option<(I1::Item, I2::IntoIter)>| match part {
+            Some((_, ys)) => ys.size_hint(),
+            None => (0, Some(0)),
+        };
+        let (front_lower, front_upper) = partial(&self.front);
+        let (back_lower, back_upper) = partial(&self.back);
+
+        let lower = rows_lower
+            .saturating_mul(row_lower)
+            .saturating_add(front_lower)
+            .saturating_add(back_lower);
+        let upper = (|| {
+            rows_upper?
+                .checked_mul(row_upper?)?
+                .checked_add(front_upper?)?
+                .checked_add(back_upper?)
+        })();
+        (lower, upper)
+    }
+
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        let (front, row, rows, back) = match self.exact_parts() {
+            Some(parts) => parts,
+            None => {
+                for _ in 0..n {
+                    self.next()?;
+                }
+                return self.next();
+            }
+        };
+
+        if n < front {
+            let (x, ys) = self.front.as_mut().unwrap();
+            return ys.nth(n).map(|y| (x.clone(), y));
+        }
+        self.front = None;
+        let n = n - front;
+
+        if row > 0 && n / row < rows {
+            let x = self.rows.nth(n / row)?;
+            let mut ys = self.iter2.clone();
+            let y = ys.nth(n % row)?;
+            self.front = Some((x.clone(), ys));
+            return Some((x, y));
+        }
+        if rows > 0 {
+            self.rows.nth(rows - 1);
+        }
+        let n = n - rows * row;
+
+        if n < back {
+            let (x, ys) = self.back.as_mut().unwrap();
+            return ys.nth(n).map(|y| (x.clone(), y));
+        }
+        self.back = None;
+        None
+    }
+}
+
+impl<I1, I2> DoubleEndedIterator for Pairwise<I1, I2>
+where
+    I1: IntoIterator<Item: Clone, IntoIter: DoubleEndedIterator>,
+    I2: IntoIterator<IntoIter: DoubleEndedIterator + Clone>,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((x, ys)) = &mut self.back {
+                if let Some(y) = ys.next_back() {
+                    return Some((x.clone(), y));
+                }
+                self.back = None;
+            }
+            match self.rows.next_back() {
+                Some(x) => self.back = Some((x, self.iter2.clone())),
+                None => {
+                    let (x, ys) = self.front.as_mut()?;
+                    let item = ys.next_back().map(|y| (x.clone(), y));
+                    if item.is_none() {
+                        self.front = None;
+                    }
+                    return item;
+                }
+            }
+        }
+    }
+}
+
+impl<I1, I2> FusedIterator for Pairwise<I1, I2>
+where
+    I1: IntoIterator<Item: Clone, IntoIter: FusedIterator>,
+    I2: IntoIterator<IntoIter: Clone>,
+{
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+
+    #[test]
+    fn empty() {
+        let empty: [i32; 0] = [];
+        let a = [1];
+
+        let mut p = Pairwise::from(&a, &empty);
+        assert_eq!(None, p.next());
+
+        let mut p = Pairwise::from(&empty, &a);
+        assert_eq!(None, p.next());
+
+        let mut p = Pairwise::from(&empty, &empty);
+        assert_eq!(None, p.next());
+    }
+
+    #[test]
+    fn single_element() {
+        let a = [1];
+        let mut p = Pairwise::from(&a, &a);
+        assert_eq!(Some((&1, &1)), p.next());
+        assert_eq!(None, p.next());
+    }
+
+    #[test]
+    fn restartable() {
+        let a = [1];
+        let mut p = Pairwise::from(&a, &a);
+        assert_eq!(Some((&1, &1)), p.next());
+        assert_eq!(None, p.next());
+        assert_eq!(None, p.next());
+        assert_eq!(None, p.next());
+    }
+
+    #[test]
+    fn same_size() {
+        let a = [1, 2];
+        let mut p = Pairwise::from(&a, &a);
+        assert_eq!(Some((&1, &1)), p.next());
+        assert_eq!(Some((&1, &2)), p.next());
+        assert_eq!(Some((&2, &1)), p.next());
+        assert_eq!(Some((&2, &2)), p.next());
+        assert_eq!(None, p.next());
+    }
+
+    #[test]
+    fn different_collections() {
+        let a = [1, 2];
+        let b = ["A", "B"];
+
+        let mut p = Pairwise::from(&a, &b);
+
+        assert_eq!(Some((&1, &"A")), p.next());
+        assert_eq!(Some((&1, &"B")), p.next());
+        assert_eq!(Some((&2, &"A")), p.next());
+        assert_eq!(Some((&2, &"B")), p.next());
+        assert_eq!(None, p.next());
+    }
+
+    #[test]
+    fn composability() {
+        let a = [1];
+        let b = ["A"];
+        let c = [()];
+
+        let pairs = Pairwise::from(&a, &b);
+        let mut triples = Pairwise::from(pairs, &c);
+
+        assert_eq!(Some(((&1, &"A"), &())), triples.next());
+        assert_eq!(None, triples.next());
+    }
+
+    #[test]
+    fn lengths() {
+        let a = [1, 2, 3];
+        let b = ["A", "B"];
+        let mut p = Pairwise::from(&a, &b);
+        assert_eq!(Some(6), p.checked_len());
+        p.next();
+        assert_eq!(Some(5), p.checked_len());
+        p.next_back();
+        assert_eq!(Some(4), p.checked_len());
+
+        let evens = (0..10).filter(|x| x % 2 == 0);
+        let p = Pairwise::from(evens, &b);
+        assert_eq!((0, Some(20)), p.size_hint());
+        assert_eq!(None, p.checked_len());
+
+        let p = Pairwise::from(0..usize::MAX, 0..3);
+        assert_eq!((usize::MAX, None), p.size_hint());
+        assert_eq!(None, p.checked_len());
+    }
+
+    #[test]
+    fn reversed() {
+        let a = [1, 2];
+        let b = ["A", "B"];
+        let p: Vec<_> = Pairwise::from(&a, &b).rev().collect();
+        assert_eq!(vec![(&2, &"B"), (&2, &"A"), (&1, &"B"), (&1, &"A")], p);
+    }
+
+    #[test]
+    fn random_access() {
+        let big: Vec<u32> = (0..1_000_000).collect();
+        let mut p = Pairwise::from(&big, &big);
+        assert_eq!(Some((&500_000, &7)), p.get(500_000_000_007));
+        assert_eq!(Some((&12, &34)), p.nth(12_000_034));
+        assert_eq!(Some((&12, &35)), p.next());
+        assert_eq!(Some((&999_999, &999_999)), p.next_back());
+        let len = p.checked_len().unwrap();
+        assert_eq!(Some((&999_999, &999_998)), p.get(len - 1));
+        assert_eq!(None, p.get(len));
+    }
+
+    /// Drives a `Pairwise` through random calls and checks each result against the list of all
+    /// pairs.
+    #[test]
+    fn matches_naive_enumeration() {
+        let mut seed: u64 = 0x2020;
+        let mut next = |m: usize| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) as usize % m
+        };
+        for _ in 0..500 {
+            let a: Vec<i64> = (0..next(6) as i64).collect();
+            let b: Vec<i64> = (0..next(6) as i64).map(|x| x * 10).collect();
+            let mut expected: VecDeque<_> = a
+                .iter()
+                .flat_map(|x| b.iter().map(move |y| (x, y)))
+                .collect();
+            let mut p = Pairwise::from(&a, &b);
+
+            for _ in 0..12 {
+                assert_eq!(Some(expected.len()), p.checked_len());
+                assert_eq!(expected.len(), p.clone().count());
+                let k = next(expected.len() + 2);
+                assert_eq!(expected.get(k).copied(), p.get(k));
+                match next(3) {
+                    0 => assert_eq!(expected.pop_front(), p.next()),
+                    1 => assert_eq!(expected.pop_back(), p.next_back()),
+                    _ => {
+                        let skipped = expected.drain(..k.min(expected.len())).count();
+                        let item = if skipped == k {
+                            expected.pop_front()
+                        } else {
+                            None
+                        };
+                        assert_eq!(item, p.nth(k));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn clone_iterators() {
+        let p: Vec<_> = Pairwise::from(1..3, "ab".chars()).collect();
+        assert_eq!(vec![(1, 'a'), (1, 'b'), (2, 'a'), (2, 'b')], p);
+
+        let squares = (1..4).map(|x| x * x);
+        let mut p = Pairwise::from(0..2, squares);
+        assert_eq!(Some(6), p.checked_len());
+        assert_eq!(Some((1, 4)), p.nth(4));
+        assert_eq!(Some((1, 9)), p.get(0));
+
+        let words = vec!["a".to_string(), "b".to_string()];
+        let p: Vec<_> = Pairwise::from(words.clone(), &words).collect();
+        assert_eq!(("b".to_string(), &words[0]), p[2]);
+        let p: Vec<_> = Pairwise::from(&words, words.clone()).collect();
+        assert_eq!((&words[1], "a".to_string()), p[2]);
+    }
+}
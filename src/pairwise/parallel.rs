@@ -0,0 +1,217 @@
+use std::{
+    iter::Take,
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use super::Pairwise;
+
+impl<I1, I2> Pairwise<I1, I2>
+where
+    I1: IntoIterator<Item: Clone, IntoIter: ExactSizeIterator + Clone>,
+    I2: IntoIterator<IntoIter: ExactSizeIterator + Clone>,
+{
+    /// Splits the remaining pairs into consecutive, disjoint runs of `size` pairs, the last one
+    /// possibly shorter. Each run starts with a single `nth`, so creating them is cheap. Returns
+    /// `None` if there are more pairs than fit in a `usize`, as the runs could not all be reached.
+    pub fn chunks(&self, size: usize) -> Option<impl Iterator<Item = Take<Self>> + '_> {
+        assert!(size > 0, "chunk size must be positive");
+        let count = self.size_hint().1?;
+        Some(
+            (0..count)
+                .step_by(size)
+                .map(move |start| self.chunk(start, size)),
+        )
+    }
+
+    fn chunk(&self, start: usize, size: usize) -> Take<Self> {
+        let mut chunk = self.clone();
+        if start > 0 {
+            chunk.nth(start - 1);
+        }
+        chunk.take(size)
+    }
+
+    /// Searches the pairs on several threads.
+    pub fn parallel(self) -> ParallelPairwise<I1, I2> {
+        ParallelPairwise {
+            pairs: self,
+            threads: thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            chunk_size: 1 << 14,
+        }
+    }
+}
+
+/// A `Pairwise` whose chunks are handed out to a scoped pool of worker threads. Searches that
+/// can stop early do so on every thread as soon as one of them succeeds.
+pub struct ParallelPairwise<I1, I2>
+where
+    I1: IntoIterator,
+    I2: IntoIterator,
+{
+    pairs: Pairwise<I1, I2>,
+    threads: usize,
+    chunk_size: usize,
+}
+
+impl<I1, I2> ParallelPairwise<I1, I2>
+where
+    I1: IntoIterator<Item: Clone + Send, IntoIter: ExactSizeIterator + Clone>,
+    I2: IntoIterator<Item: Send, IntoIter: ExactSizeIterator + Clone>,
+    Pairwise<I1, I2>: Sync,
+{
+    /// Defaults to the available parallelism.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// How many pairs a worker takes at a time.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Some pair matching `predicate`, not necessarily the first one.
+    pub fn find_any<P>(&self, predicate: P) -> Option<(I1::Item, I2::Item)>
+    where
+        P: Fn(&(I1::Item, I2::Item)) -> bool + Sync,
+    {
+        let count = match self.pairs.size_hint().1 {
+            Some(count) => count,
+            None => return self.pairs.clone().find(predicate),
+        };
+        let found = Mutex::new(None);
+        self.run(count, |_, chunk, stop| {
+            let item = chunk
+                .take_while(|_| !stop.load(Ordering::Relaxed))
+                .find(&predicate);
+            if let Some(item) = item {
+                stop.store(true, Ordering::Relaxed);
+                *found.lock().unwrap() = Some(item);
+            }
+        });
+        found.into_inner().unwrap()
+    }
+
+    pub fn any<P>(&self, predicate: P) -> bool
+    where
+        P: Fn(&(I1::Item, I2::Item)) -> bool + Sync,
+    {
+        self.find_any(predicate).is_some()
+    }
+
+    /// Every pair matching `predicate`, in the same order as the sequential iterator.
+    pub fn filter_collect<P>(&self, predicate: P) -> Vec<(I1::Item, I2::Item)>
+    where
+        P: Fn(&(I1::Item, I2::Item)) -> bool + Sync,
+    {
+        let count = match self.pairs.size_hint().1 {
+            Some(count) => count,
+            None => return self.pairs.clone().filter(predicate).collect(),
+        };
+        let found = Mutex::new(Vec::new());
+        self.run(count, |index, chunk, _| {
+            let matches: Vec<_> = chunk.filter(&predicate).collect();
+            if !matches.is_empty() {
+                found.lock().unwrap().push((index, matches));
+            }
+        });
+        let mut found = found.into_inner().unwrap();
+        found.sort_unstable_by_key(|(index, _)| *index);
+        found.into_iter().flat_map(|(_, matches)| matches).collect()
+    }
+
+    /// Calls `work` with the index of every chunk of the `count` pairs and the chunk itself, from
+    /// `threads` workers, until the chunks run out or the flag passed to `work` is set. Pairs
+    /// that cannot be counted in a `usize` cannot be split, so callers search those on the
+    /// current thread instead.
+    fn run<W>(&self, count: usize, work: W)
+    where
+        W: Fn(usize, Take<Pairwise<I1, I2>>, &AtomicBool) + Sync,
+    {
+        let chunks = count.div_ceil(self.chunk_size);
+        let next = AtomicUsize::new(0);
+        let stop = AtomicBool::new(false);
+        thread::scope(|s| {
+            for _ in 0..self.threads.min(chunks) {
+                s.spawn(|| {
+                    while !stop.load(Ordering::Relaxed) {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        if index >= chunks {
+                            break;
+                        }
+                        let chunk = self.pairs.chunk(index * self.chunk_size, self.chunk_size);
+                        work(index, chunk, &stop);
+                    }
+                });
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_cover_everything_once() {
+        let a: Vec<u32> = (0..7).collect();
+        let b: Vec<u32> = (0..5).collect();
+        let mut p = Pairwise::from(&a, &b);
+        p.next();
+        let all: Vec<_> = p.clone().collect();
+        for size in 1..40 {
+            let chunks: Vec<Vec<_>> = p.chunks(size).unwrap().map(Iterator::collect).collect();
+            assert!(chunks.iter().all(|c| !c.is_empty() && c.len() <= size));
+            assert_eq!(all, chunks.concat());
+        }
+    }
+
+    #[test]
+    fn searches() {
+        let data: Vec<u64> = (0..1000).collect();
+        let p = Pairwise::from(&data, &data)
+            .parallel()
+            .threads(4)
+            .chunk_size(999);
+        assert_eq!(
+            Some((&999, &998)),
+            p.find_any(|(&x, &y)| x * y == 999 * 998 && x > y)
+        );
+        assert!(p.any(|(&x, &y)| x + y == 1997));
+        assert!(!p.any(|(&x, &y)| x + y == 1999));
+        assert_eq!(
+            vec![
+                (&1, &999),
+                (&2, &998),
+                (&250, &750),
+                (&500, &500),
+                (&750, &250),
+                (&999, &1)
+            ],
+            p.filter_collect(|(&x, &y)| x + y == 1000 && (x < 3 || x % 250 == 0 || y == 1))
+        );
+    }
+
+    #[test]
+    fn too_many_to_split() {
+        let p = Pairwise::from(0..usize::MAX, 0..3);
+        assert!(p.chunks(1 << 14).is_none());
+        let p = p.parallel();
+        assert_eq!(Some((5, 2)), p.find_any(|&(x, y)| x == 5 && y == 2));
+    }
+
+    #[test]
+    fn empty() {
+        let a = [1];
+        let empty: [i32; 0] = [];
+        let p = Pairwise::from(&a, &empty).parallel();
+        assert!(!p.any(|_| true));
+        assert!(p.filter_collect(|_| true).is_empty());
+    }
+}
@@ -0,0 +1,148 @@
+use std::iter::FusedIterator;
+
+/// Every combination of one element from each of `N` slices, as flat arrays in lexicographic
+/// order of their indices. `Pairwise` is the two-way special case for arbitrary iterators.
+/// Products too large to count in a `usize` can still be iterated, which is why the count is
+/// only available through `checked_len`.
+///
+/// ```
+/// use aoc_2020::pairwise::Product;
+///
+/// let data = [1, 2, 3];
+/// let triple = Product::new([&data[..], &data, &data]).find(|[x, y, z]| *x + *y + *z == 9);
+/// assert_eq!(Some([&3, &3, &3]), triple);
+/// ```
+#[derive(Debug, Clone)]
+pub struct Product<'a, T, const N: usize> {
+    slices: [&'a [T]; N],
+    indices: [usize; N],
+    /// `None` while the count does not fit in a `usize`.
+    remaining: Option<usize>,
+}
+
+impl<'a, T, const N: usize> Product<'a, T, N> {
+    pub fn new(slices: [&'a [T]; N]) -> Self {
+        let remaining = if slices.iter().any(|s| s.is_empty()) {
+            Some(0)
+        } else {
+            slices
+                .iter()
+                .try_fold(1usize, |n, s| n.checked_mul(s.len()))
+        };
+        Product {
+            slices,
+            indices: [0; N],
+            remaining,
+        }
+    }
+
+    /// The number of combinations left, or `None` if it does not fit in a `usize`.
+    pub fn checked_len(&self) -> Option<usize> {
+        self.remaining
+    }
+}
+
+impl<'a, T, const N: usize> Iterator for Product<'a, T, N> {
+    type Item = [&'a T; N];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == Some(0) {
+            return None;
+        }
+        let item = std::array::from_fn(|i| &self.slices[i][self.indices[i]]);
+        self.remaining = self.remaining.map(|n| n - 1);
+        // Advance like an odometer, the last slice turning fastest. Once every wheel has turned
+        // over, everything has been seen.
+        for i in (0..N).rev() {
+            self.indices[i] += 1;
+            if self.indices[i] < self.slices[i].len() {
+                return Some(item);
+            }
+            self.indices[i] = 0;
+        }
+        self.remaining = Some(0);
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.remaining {
+            Some(n) => (n, Some(n)),
+            None => (0, None),
+        }
+    }
+}
+
+impl<T, const N: usize> FusedIterator for Product<'_, T, N> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pairwise::Pairwise;
+
+    #[test]
+    fn triples() {
+        let a = [1, 2];
+        let b = ["A"];
+        let c = [3, 4];
+        let p: Vec<_> = Product::new([&a[..], &c, &a]).collect();
+        assert_eq!(
+            vec![
+                [&1, &3, &1],
+                [&1, &3, &2],
+                [&1, &4, &1],
+                [&1, &4, &2],
+                [&2, &3, &1],
+                [&2, &3, &2],
+                [&2, &4, &1],
+                [&2, &4, &2],
+            ],
+            p
+        );
+        assert_eq!(1, Product::new([&b[..]]).count());
+    }
+
+    #[test]
+    fn matches_pairwise() {
+        let a = [5, 6, 7];
+        let b = [8, 9];
+        let pairs: Vec<_> = Pairwise::from(&a, &b).map(|(x, y)| [x, y]).collect();
+        let product: Vec<_> = Product::new([&a[..], &b]).collect();
+        assert_eq!(pairs, product);
+    }
+
+    #[test]
+    fn sizes() {
+        let a = [1, 2, 3];
+        let empty: [i32; 0] = [];
+        let mut p = Product::new([&a[..], &a, &a, &a]);
+        assert_eq!(Some(81), p.checked_len());
+        p.next();
+        assert_eq!((80, Some(80)), p.size_hint());
+        assert_eq!(Some(0), Product::new([&a[..], &empty, &a]).checked_len());
+        assert_eq!(1, Product::<i32, 0>::new([]).count());
+    }
+
+    #[test]
+    fn too_large_to_count() {
+        let big: Vec<u32> = (0..10_000).collect();
+        let mut p = Product::new([&big[..], &big, &big, &big, &big]);
+        assert_eq!((0, None), p.size_hint());
+        assert_eq!(None, p.checked_len());
+        assert_eq!(Some([&0, &0, &0, &1, &2]), p.nth(10_002));
+        let found = p.find(|[_, _, _, d, e]| **d == 2 && **e == 7);
+        assert_eq!(Some([&0, &0, &0, &2, &7]), found);
+
+        let small = [1, 2];
+        let p = Product::new([&big[..], &big, &big, &big, &big, &[][..], &small]);
+        assert_eq!(Some(0), p.checked_len());
+    }
+
+    #[test]
+    fn elements_need_not_be_copy() {
+        let words = vec!["a".to_string(), "b".to_string()];
+        let joined: Vec<String> = Product::new([&words[..], &words])
+            .map(|[x, y]| format!("{}{}", x, y))
+            .collect();
+        assert_eq!(vec!["aa", "ab", "ba", "bb"], joined);
+    }
+}